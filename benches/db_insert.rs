@@ -0,0 +1,110 @@
+//! Compares `DbLogger`'s per-row and bulk chat inserts against a local Postgres.
+//!
+//! Run with `DATABASE_URL=postgres://... cargo bench --bench db_insert`. The benchmark works in
+//! its own `bench_chat_*` tables and drops them afterwards.
+
+use chrono::Utc;
+use sqlx::PgPool;
+use std::time::Instant;
+use twitch_logger::config::Config;
+use twitch_logger::entities::chat::ChatMessage;
+use twitch_logger::entities::event::Event;
+use twitch_logger::logger::db_logger::DbLogger;
+use twitch_logger::logger::storage::Storage;
+
+const ROWS: usize = 20_000;
+const BATCH_SIZES: [usize; 4] = [10, 100, 1_000, 5_000];
+const SIDE_TABLES: [&str; 8] = [
+    "user_notices",
+    "moderation",
+    "room_state",
+    "presence",
+    "presence_sessions",
+    "gaps",
+    "lifecycle",
+    "migrations",
+];
+
+#[tokio::main]
+async fn main() {
+    let db_url = match std::env::var("DATABASE_URL") {
+        Ok(db_url) => db_url,
+        Err(_) => {
+            println!("DATABASE_URL is not set, skipping");
+            return;
+        }
+    };
+    let pool = PgPool::connect(&db_url).await.unwrap();
+    let table = format!("bench_chat_{}", std::process::id());
+
+    sqlx::query(&format!(
+        "CREATE TABLE {} (username TEXT, message TEXT, channel TEXT, sent_at TIMESTAMPTZ)",
+        table
+    ))
+    .execute(&pool)
+    .await
+    .unwrap();
+    let config = Config {
+        db_table: Some(table.clone()),
+        ..Config::default()
+    };
+    DbLogger::new(&config, pool.clone())
+        .unwrap()
+        .migrate()
+        .await
+        .unwrap();
+
+    println!(
+        "{:>10} {:>16} {:>16}",
+        "batch", "per-row rows/s", "bulk rows/s"
+    );
+    for batch_size in BATCH_SIZES {
+        let per_row = DbLogger::new(&config, pool.clone())
+            .unwrap()
+            .with_bulk_insert_threshold(usize::MAX);
+        let bulk = DbLogger::new(&config, pool.clone())
+            .unwrap()
+            .with_bulk_insert_threshold(1);
+        println!(
+            "{:>10} {:>16.0} {:>16.0}",
+            batch_size,
+            rows_per_second(&pool, &table, per_row, batch_size).await,
+            rows_per_second(&pool, &table, bulk, batch_size).await
+        );
+    }
+
+    for suffix in SIDE_TABLES {
+        let query = format!("DROP TABLE IF EXISTS {}_{}", table, suffix);
+        sqlx::query(&query).execute(&pool).await.unwrap();
+    }
+    let query = format!("DROP TABLE {}", table);
+    sqlx::query(&query).execute(&pool).await.unwrap();
+}
+
+async fn rows_per_second(
+    pool: &PgPool,
+    table: &str,
+    mut db_logger: DbLogger,
+    batch_size: usize,
+) -> f64 {
+    let query = format!("TRUNCATE {}", table);
+    sqlx::query(&query).execute(pool).await.unwrap();
+    let events = (0..ROWS).map(chat_message).collect::<Vec<_>>();
+
+    let started_at = Instant::now();
+    for batch in events.chunks(batch_size) {
+        db_logger.create_log_batch(batch).await.unwrap();
+    }
+    ROWS as f64 / started_at.elapsed().as_secs_f64()
+}
+
+fn chat_message(i: usize) -> Event {
+    let mut message = ChatMessage::new(
+        "forsen".to_string(),
+        format!("user{}", i % 500),
+        format!("message number {} with some typical chat text LUL", i),
+        Utc::now(),
+    );
+    message.message_id = Some(i.to_string());
+    Event::Chat(message)
+}
@@ -0,0 +1,7 @@
+-- The original chat layout. Tables created by hand before migrations existed are kept as is.
+CREATE TABLE IF NOT EXISTS {table} (
+    username TEXT NOT NULL,
+    message TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,13 @@
+ALTER TABLE {table}
+    ADD COLUMN IF NOT EXISTS message_id TEXT,
+    ADD COLUMN IF NOT EXISTS user_id TEXT,
+    ADD COLUMN IF NOT EXISTS display_name TEXT,
+    ADD COLUMN IF NOT EXISTS color TEXT,
+    ADD COLUMN IF NOT EXISTS badges JSONB,
+    ADD COLUMN IF NOT EXISTS badge_info JSONB,
+    ADD COLUMN IF NOT EXISTS emotes JSONB,
+    ADD COLUMN IF NOT EXISTS bits BIGINT,
+    ADD COLUMN IF NOT EXISTS reply_parent_id TEXT,
+    ADD COLUMN IF NOT EXISTS reply_parent JSONB,
+    ADD COLUMN IF NOT EXISTS room_id TEXT,
+    ADD COLUMN IF NOT EXISTS raw TEXT;
@@ -0,0 +1,71 @@
+CREATE TABLE IF NOT EXISTS {table:user_notices} (
+    channel TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    event JSONB NOT NULL,
+    system_message TEXT NOT NULL,
+    message TEXT,
+    color TEXT,
+    badges JSONB NOT NULL,
+    badge_info JSONB NOT NULL,
+    emotes JSONB NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL,
+    raw TEXT
+);
+
+CREATE TABLE IF NOT EXISTS {table:moderation} (
+    channel TEXT NOT NULL,
+    room_id TEXT,
+    action TEXT NOT NULL,
+    target_login TEXT,
+    target_user_id TEXT,
+    target_message_id TEXT,
+    duration_seconds BIGINT,
+    message TEXT,
+    sent_at TIMESTAMPTZ NOT NULL,
+    raw TEXT
+);
+
+-- followers_only_minutes uses Twitch's encoding: -1 is disabled, NULL is unchanged.
+CREATE TABLE IF NOT EXISTS {table:room_state} (
+    channel TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    emote_only BOOLEAN,
+    followers_only_minutes BIGINT,
+    slow_mode_seconds BIGINT,
+    subs_only BOOLEAN,
+    r9k BOOLEAN,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    raw TEXT
+);
+
+CREATE TABLE IF NOT EXISTS {table:presence} (
+    channel TEXT NOT NULL,
+    username TEXT NOT NULL,
+    action TEXT NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL,
+    raw TEXT
+);
+
+CREATE TABLE IF NOT EXISTS {table:presence_sessions} (
+    channel TEXT NOT NULL,
+    username TEXT NOT NULL,
+    joined_at TIMESTAMPTZ NOT NULL,
+    left_at TIMESTAMPTZ,
+    close_reason TEXT
+);
+
+-- Event tables created before raw lines were stored.
+ALTER TABLE {table:user_notices} ADD COLUMN IF NOT EXISTS raw TEXT;
+ALTER TABLE {table:moderation} ADD COLUMN IF NOT EXISTS raw TEXT;
+ALTER TABLE {table:room_state} ADD COLUMN IF NOT EXISTS raw TEXT;
+ALTER TABLE {table:presence} ADD COLUMN IF NOT EXISTS raw TEXT;
+
+CREATE INDEX IF NOT EXISTS {name:room_state_channel_recorded_at}
+    ON {table:room_state} (channel, recorded_at);
+CREATE INDEX IF NOT EXISTS {name:presence_sessions_channel_joined_at}
+    ON {table:presence_sessions} (channel, joined_at);
@@ -0,0 +1,16 @@
+-- The rows 0004_unique_keys.sql would reject, as (table, duplicates beyond the first copy).
+SELECT 'chat messages', CAST(COALESCE(SUM(copies - 1), 0) AS BIGINT) FROM (
+    SELECT COUNT(*) AS copies FROM {table} WHERE message_id IS NOT NULL
+    GROUP BY message_id HAVING COUNT(*) > 1
+) duplicates
+UNION ALL
+SELECT 'user notices', CAST(COALESCE(SUM(copies - 1), 0) AS BIGINT) FROM (
+    SELECT COUNT(*) AS copies FROM {table:user_notices}
+    GROUP BY message_id HAVING COUNT(*) > 1
+) duplicates
+UNION ALL
+SELECT 'moderation events', CAST(COALESCE(SUM(copies - 1), 0) AS BIGINT) FROM (
+    SELECT COUNT(*) AS copies FROM {table:moderation}
+    GROUP BY channel, action, COALESCE(target_login, ''), COALESCE(target_message_id, ''), sent_at
+    HAVING COUNT(*) > 1
+) duplicates
@@ -0,0 +1,2 @@
+-- /me messages, whose text is stored without the ACTION wrapper.
+ALTER TABLE {table} ADD COLUMN IF NOT EXISTS is_action BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,10 @@
+-- Events the logger dropped because it could not keep up, one row per channel and overflow.
+CREATE TABLE IF NOT EXISTS {table:gaps} (
+    channel TEXT NOT NULL,
+    dropped BIGINT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS {name:gaps_channel_started_at_key}
+    ON {table:gaps} (channel, started_at);
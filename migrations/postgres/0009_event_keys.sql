@@ -0,0 +1,8 @@
+-- Reject room state changes and presence events logged twice, as replaying the spool or a log
+-- file would. Like 0004_unique_keys.sql, this refuses to run over existing duplicates (see
+-- 0009_event_keys_check.sql) rather than deleting them.
+CREATE UNIQUE INDEX IF NOT EXISTS {name:room_state_event_key}
+    ON {table:room_state} (channel, recorded_at);
+
+CREATE UNIQUE INDEX IF NOT EXISTS {name:presence_event_key}
+    ON {table:presence} (channel, username, action, recorded_at);
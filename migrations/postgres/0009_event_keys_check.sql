@@ -0,0 +1,10 @@
+-- The rows 0009_event_keys.sql would reject, as (table, duplicates beyond the first copy).
+SELECT 'room state changes', CAST(COALESCE(SUM(copies - 1), 0) AS BIGINT) FROM (
+    SELECT COUNT(*) AS copies FROM {table:room_state}
+    GROUP BY channel, recorded_at HAVING COUNT(*) > 1
+) duplicates
+UNION ALL
+SELECT 'presence events', CAST(COALESCE(SUM(copies - 1), 0) AS BIGINT) FROM (
+    SELECT COUNT(*) AS copies FROM {table:presence}
+    GROUP BY channel, username, action, recorded_at HAVING COUNT(*) > 1
+) duplicates
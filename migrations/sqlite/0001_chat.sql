@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS {table} (
+    username TEXT NOT NULL,
+    message TEXT NOT NULL,
+    channel TEXT NOT NULL,
+    sent_at TEXT NOT NULL
+);
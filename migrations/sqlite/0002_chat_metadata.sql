@@ -0,0 +1,13 @@
+-- SQLite adds one column per statement. Timestamps are stored as text and JSON as text.
+ALTER TABLE {table} ADD COLUMN message_id TEXT;
+ALTER TABLE {table} ADD COLUMN user_id TEXT;
+ALTER TABLE {table} ADD COLUMN display_name TEXT;
+ALTER TABLE {table} ADD COLUMN color TEXT;
+ALTER TABLE {table} ADD COLUMN badges TEXT;
+ALTER TABLE {table} ADD COLUMN badge_info TEXT;
+ALTER TABLE {table} ADD COLUMN emotes TEXT;
+ALTER TABLE {table} ADD COLUMN bits INTEGER;
+ALTER TABLE {table} ADD COLUMN reply_parent_id TEXT;
+ALTER TABLE {table} ADD COLUMN reply_parent TEXT;
+ALTER TABLE {table} ADD COLUMN room_id TEXT;
+ALTER TABLE {table} ADD COLUMN raw TEXT;
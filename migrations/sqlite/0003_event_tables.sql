@@ -0,0 +1,65 @@
+CREATE TABLE IF NOT EXISTS {table:user_notices} (
+    channel TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    username TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    message_id TEXT NOT NULL,
+    event_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    system_message TEXT NOT NULL,
+    message TEXT,
+    color TEXT,
+    badges TEXT NOT NULL,
+    badge_info TEXT NOT NULL,
+    emotes TEXT NOT NULL,
+    sent_at TEXT NOT NULL,
+    raw TEXT
+);
+
+CREATE TABLE IF NOT EXISTS {table:moderation} (
+    channel TEXT NOT NULL,
+    room_id TEXT,
+    action TEXT NOT NULL,
+    target_login TEXT,
+    target_user_id TEXT,
+    target_message_id TEXT,
+    duration_seconds INTEGER,
+    message TEXT,
+    sent_at TEXT NOT NULL,
+    raw TEXT
+);
+
+-- followers_only_minutes uses Twitch's encoding: -1 is disabled, NULL is unchanged.
+CREATE TABLE IF NOT EXISTS {table:room_state} (
+    channel TEXT NOT NULL,
+    room_id TEXT NOT NULL,
+    emote_only BOOLEAN,
+    followers_only_minutes INTEGER,
+    slow_mode_seconds INTEGER,
+    subs_only BOOLEAN,
+    r9k BOOLEAN,
+    recorded_at TEXT NOT NULL,
+    raw TEXT
+);
+
+CREATE TABLE IF NOT EXISTS {table:presence} (
+    channel TEXT NOT NULL,
+    username TEXT NOT NULL,
+    action TEXT NOT NULL,
+    recorded_at TEXT NOT NULL,
+    raw TEXT
+);
+
+CREATE TABLE IF NOT EXISTS {table:presence_sessions} (
+    channel TEXT NOT NULL,
+    username TEXT NOT NULL,
+    joined_at TEXT NOT NULL,
+    left_at TEXT,
+    close_reason TEXT
+);
+
+CREATE INDEX IF NOT EXISTS {name:room_state_channel_recorded_at}
+    ON {table:room_state} (channel, recorded_at);
+CREATE INDEX IF NOT EXISTS {name:presence_sessions_channel_joined_at}
+    ON {table:presence_sessions} (channel, joined_at);
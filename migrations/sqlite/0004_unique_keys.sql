@@ -0,0 +1,12 @@
+-- Reject duplicates from now on. Existing duplicates are never deleted here: the migration's
+-- check (0004_unique_keys_check.sql) refuses to run it while there are any, and reports how
+-- many. Chat logged before message ids were has a NULL id and is left alone.
+CREATE UNIQUE INDEX IF NOT EXISTS {name:message_id_key} ON {table} (message_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS {name:user_notices_message_id_key}
+    ON {table:user_notices} (message_id);
+
+-- Moderation events carry no id of their own; Twitch's timestamp tells repeats apart.
+CREATE UNIQUE INDEX IF NOT EXISTS {name:moderation_event_key} ON {table:moderation} (
+    channel, action, COALESCE(target_login, ''), COALESCE(target_message_id, ''), sent_at
+);
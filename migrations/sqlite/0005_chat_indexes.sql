@@ -0,0 +1,2 @@
+CREATE INDEX IF NOT EXISTS {name:channel_sent_at} ON {table} (channel, sent_at);
+CREATE INDEX IF NOT EXISTS {name:username_sent_at} ON {table} (username, sent_at);
@@ -0,0 +1,11 @@
+-- When the logger started, stopped, connected, joined and lost each channel.
+CREATE TABLE IF NOT EXISTS {table:lifecycle} (
+    channel TEXT NOT NULL,
+    event TEXT NOT NULL,
+    version TEXT,
+    reason TEXT,
+    recorded_at TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS {name:lifecycle_channel_event_recorded_at_key}
+    ON {table:lifecycle} (channel, event, recorded_at);
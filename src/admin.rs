@@ -0,0 +1,102 @@
+use crate::client::ClientHandle;
+use crate::error::Error;
+use crate::handler::{HandlerHandle, Stats};
+use crate::queue::{QueueHandle, QueueStats};
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use log::info;
+use serde_json::json;
+use std::net::SocketAddr;
+
+/// A local HTTP API to manage the running logger.
+///
+/// - `GET /channels`: the channels the client is in
+/// - `PUT /channels/:channel`, `DELETE /channels/:channel`: join or part a channel
+/// - `POST /flush`: write the buffered events now
+/// - `GET /stats`: per-channel counters and the time of the last message
+/// - `GET /queue`: events waiting for the handler, and those dropped or delayed by channel
+#[derive(Clone)]
+pub struct AdminApi {
+    client: ClientHandle,
+    handler: HandlerHandle,
+    queue: QueueHandle,
+}
+
+impl AdminApi {
+    pub fn new(client: ClientHandle, handler: HandlerHandle, queue: QueueHandle) -> Self {
+        Self {
+            client,
+            handler,
+            queue,
+        }
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/channels", get(list_channels))
+            .route("/channels/:channel", put(join_channel).delete(part_channel))
+            .route("/flush", post(flush))
+            .route("/stats", get(stats))
+            .route("/queue", get(queue))
+            .with_state(self)
+    }
+
+    pub async fn serve(self, address: SocketAddr) -> Result<(), Error> {
+        info!("Admin API listening on {}", address);
+        axum::Server::try_bind(&address)
+            .map_err(|e| Error::Other(format!("Failed to bind {}: {}", address, e)))?
+            .serve(self.router().into_make_service())
+            .await
+            .map_err(|e| Error::Other(format!("Admin API failed: {}", e)))
+    }
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Error::FailedToParse { .. } => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+async fn list_channels(State(api): State<AdminApi>) -> Json<Vec<String>> {
+    Json(api.client.channels())
+}
+
+async fn join_channel(
+    State(api): State<AdminApi>,
+    Path(channel): Path<String>,
+) -> Result<StatusCode, Error> {
+    match api.client.join(&channel)? {
+        true => Ok(StatusCode::CREATED),
+        false => Ok(StatusCode::OK),
+    }
+}
+
+async fn part_channel(
+    State(api): State<AdminApi>,
+    Path(channel): Path<String>,
+) -> Result<StatusCode, Error> {
+    match api.client.part(&channel)? {
+        true => Ok(StatusCode::OK),
+        false => Ok(StatusCode::NOT_FOUND),
+    }
+}
+
+async fn flush(State(api): State<AdminApi>) -> Result<Json<serde_json::Value>, Error> {
+    let written = api.handler.flush().await?;
+    Ok(Json(json!({ "written": written })))
+}
+
+async fn stats(State(api): State<AdminApi>) -> Json<Stats> {
+    Json(api.handler.stats())
+}
+
+async fn queue(State(api): State<AdminApi>) -> Json<QueueStats> {
+    Json(api.queue.stats().await)
+}
@@ -1,81 +1,344 @@
 use crate::config::Config;
+use crate::credentials::Credentials;
 use crate::error::Error;
-use crate::utils;
-use log::{debug, trace};
-use tokio::sync::mpsc::{Receiver, Sender};
+use crate::queue::EventSender;
+use crate::transport::{connection_id, LoggerTransport, DISCONNECTED_COMMAND};
+use log::{debug, info, trace, warn};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
 
-use crate::entities::chat::ChatMessage;
-use twitch_irc::login::RefreshingLoginCredentials;
+use crate::entities::event::Event;
+use crate::entities::lifecycle::{Lifecycle, LifecycleKind};
+use twitch_irc::login::LoginCredentials;
 use twitch_irc::message::ServerMessage;
+use twitch_irc::transport::tcp::TLS;
+use twitch_irc::transport::Transport;
+use twitch_irc::validate::validate_login;
+use twitch_irc::ClientConfig;
 use twitch_irc::TwitchIRCClient;
-use twitch_irc::{ClientConfig, SecureTCPTransport};
-use utils::env::EnvStorage;
 
-pub struct Client {
-    channels: Vec<String>,
-    env: EnvStorage,
-    irc: Option<TwitchIRCClient<SecureTCPTransport, RefreshingLoginCredentials<EnvStorage>>>,
+/// The transport used to connect to Twitch.
+pub type TwitchTransport = LoggerTransport<TLS>;
+
+/// Joins `channels` and forwards every logged event to the handler. The channels can be changed
+/// while the client runs through its `ClientHandle`.
+///
+/// Along with them it forwards a `Lifecycle` event per channel whenever a connection is opened
+/// or closed and whenever Twitch confirms a join or part.
+///
+/// `T` is the transport the connections are made with and `L` the credentials to log in with;
+/// both can be swapped out, e.g. to connect to a local IRC server instead of Twitch.
+pub struct Client<T: Transport = TwitchTransport, L: LoginCredentials = Credentials> {
+    handle: ClientHandle,
+    credentials: L,
+    store_raw: bool,
+    irc: Option<TwitchIRCClient<T, L>>,
 }
 
-impl Client {
-    pub fn new(channels: Vec<String>, env: EnvStorage) -> Self {
+impl<T: Transport, L: LoginCredentials + Clone> Client<T, L> {
+    pub fn new(channels: Vec<String>, credentials: L, store_raw: bool) -> Self {
+        let handle = ClientHandle::default();
+        for channel in &channels {
+            if let Err(e) = handle.join(channel) {
+                warn!("Not joining {}: {}", channel, e);
+            }
+        }
+
         Self {
-            channels,
-            env,
+            handle,
+            credentials,
+            store_raw,
             irc: None,
         }
     }
 
-    pub async fn start(&mut self, sender: Sender<ChatMessage>) -> Result<(), Error> {
-        let config = build_irc_config(self.env.clone())?;
+    pub fn handle(&self) -> ClientHandle {
+        self.handle.clone()
+    }
+
+    /// Forwards the events of the wanted channels to `sender`. Returns once the client is stopped,
+    /// the connection ends or the handler stops taking events.
+    pub async fn start(&mut self, sender: EventSender) -> Result<(), Error> {
+        let config = ClientConfig::new_simple(self.credentials.clone());
 
-        let (mut incoming_messages, irc) = TwitchIRCClient::<
-            SecureTCPTransport,
-            RefreshingLoginCredentials<EnvStorage>,
-        >::new(config);
+        let (mut incoming_messages, irc) = TwitchIRCClient::<T, L>::new(config);
         self.irc = Some(irc);
 
-        let join_handle = tokio::spawn(async move {
-            while let Some(message) = incoming_messages.recv().await {
+        let store_raw = self.store_raw;
+        let mut connections = Connections::new(self.handle.clone());
+        let mut join_handle = tokio::spawn(async move {
+            'messages: while let Some(message) = incoming_messages.recv().await {
                 trace!("{:?}", message);
-                if let ServerMessage::Privmsg(msg) = message {
-                    let msg = ChatMessage::from(msg);
-                    sender.send(msg).await.unwrap();
+                for lifecycle in connections.observe(&message) {
+                    info!("{}", lifecycle);
+                    if let Err(e) = sender.send(Event::Lifecycle(lifecycle)).await {
+                        warn!("{}, no longer reading messages", e);
+                        break 'messages;
+                    }
+                }
+                if let Some(mut event) = Event::from_server_message(message) {
+                    if !store_raw {
+                        event.clear_raw();
+                    }
+                    if let Err(e) = sender.send(event).await {
+                        warn!("{}, no longer reading messages", e);
+                        break 'messages;
+                    }
                 }
             }
         });
 
-        let irc = self.irc.as_mut().unwrap();
-        for channel in &self.channels {
-            debug!("Joining channel: {}", channel);
-            irc.join(channel.to_string()).unwrap();
+        let irc = self.irc.as_ref().unwrap();
+        let mut joined = HashSet::new();
+        loop {
+            let stopping = self.handle.is_stopping();
+            let wanted = if stopping {
+                HashSet::new()
+            } else {
+                self.handle.wanted_channels()
+            };
+            for channel in wanted.difference(&joined) {
+                debug!("Joining channel: {}", channel);
+                irc.join(channel.to_string()).unwrap();
+            }
+            for channel in joined.difference(&wanted) {
+                debug!("Parting channel: {}", channel);
+                irc.part(channel.to_string());
+            }
+            joined = wanted;
+
+            if stopping {
+                // Dropping the last client closes the connection, which ends the incoming
+                // messages once the ones already received are forwarded.
+                self.irc = None;
+                join_handle.await.unwrap();
+                return Ok(());
+            }
+
+            tokio::select! {
+                result = &mut join_handle => {
+                    result.unwrap();
+                    return Ok(());
+                }
+                _ = self.handle.changed.notified() => {}
+            }
         }
+    }
+}
 
-        join_handle.await.unwrap();
+impl TryFrom<&Config> for Client {
+    type Error = Error;
 
-        Ok(())
+    fn try_from(config: &Config) -> Result<Self, Self::Error> {
+        let channels = config.channels.clone().unwrap().keys().cloned().collect();
+        let credentials = Credentials::try_from(config)?;
+        Ok(Self::new(
+            channels,
+            credentials,
+            config.store_raw.unwrap_or_default(),
+        ))
     }
 }
 
-fn build_irc_config(
-    env: EnvStorage,
-) -> Result<ClientConfig<RefreshingLoginCredentials<EnvStorage>>, Error> {
-    let username: Option<String> = env
-        .get_env_opt("USERNAME")?
-        .filter(|s: &String| !s.is_empty());
+/// Changes the channels a running `Client` is in.
+///
+/// Channels are kept as a wanted set: the client joins and parts to match it, and twitch-irc
+/// rejoins all of them after a reconnect.
+#[derive(Debug, Clone, Default)]
+pub struct ClientHandle {
+    channels: Arc<Mutex<BTreeSet<String>>>,
+    changed: Arc<Notify>,
+    stopping: Arc<AtomicBool>,
+}
+
+impl ClientHandle {
+    /// Adds `channel` to the wanted channels. Returns `false` if it was already there.
+    pub fn join(&self, channel: &str) -> Result<bool, Error> {
+        let channel = channel_login(channel)?;
+        let added = self.channels.lock().unwrap().insert(channel.clone());
+        if added {
+            info!("Joining channel: {}", channel);
+            self.changed.notify_one();
+        }
+        Ok(added)
+    }
+
+    /// Removes `channel` from the wanted channels. Returns `false` if it was not there.
+    pub fn part(&self, channel: &str) -> Result<bool, Error> {
+        let channel = channel_login(channel)?;
+        let removed = self.channels.lock().unwrap().remove(&channel);
+        if removed {
+            info!("Parting channel: {}", channel);
+            self.changed.notify_one();
+        }
+        Ok(removed)
+    }
+
+    /// Replaces the wanted channels with `channels`.
+    pub fn set_channels<I, S>(&self, channels: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let channels = channels
+            .into_iter()
+            .map(|channel| channel_login(channel.as_ref()))
+            .collect::<Result<BTreeSet<_>, _>>()?;
+        *self.channels.lock().unwrap() = channels;
+        self.changed.notify_one();
+        Ok(())
+    }
+
+    /// Parts every channel and disconnects. `Client::start` returns once the events already
+    /// received are sent to the handler, dropping its sender.
+    pub fn stop(&self) {
+        info!("Stopping client");
+        self.stopping.store(true, Ordering::SeqCst);
+        self.changed.notify_one();
+    }
+
+    fn is_stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
 
-    let client_id = env.get_env("CLIENT_ID")?;
-    let client_secret = env.get_env("CLIENT_SECRET")?;
+    /// The wanted channels, sorted.
+    pub fn channels(&self) -> Vec<String> {
+        self.channels.lock().unwrap().iter().cloned().collect()
+    }
 
-    Ok(ClientConfig::new_simple(
-        RefreshingLoginCredentials::init_with_username(username, client_id, client_secret, env),
-    ))
+    fn wanted_channels(&self) -> HashSet<String> {
+        self.channels.lock().unwrap().iter().cloned().collect()
+    }
 }
 
-impl From<&Config> for Client {
-    fn from(config: &Config) -> Self {
-        let env = EnvStorage::from(config);
-        let channels = config.channels.clone().unwrap().keys().cloned().collect();
-        Self::new(channels, env)
+/// The connections twitch-irc has open, tracked from the messages they receive so that their
+/// opening and closing can be logged for each channel.
+///
+/// Relies on `LoggerTransport`, which tags every message with its connection and marks the end
+/// of each connection.
+struct Connections {
+    handle: ClientHandle,
+    /// The nick each open connection logged in as.
+    nicks: HashMap<u64, String>,
+    /// The connection each joined channel was joined on.
+    channels: HashMap<String, u64>,
+    /// Whether a connection was lost, which makes the next one a reconnect.
+    lost: bool,
+}
+
+impl Connections {
+    fn new(handle: ClientHandle) -> Self {
+        Self {
+            handle,
+            nicks: HashMap::new(),
+            channels: HashMap::new(),
+            lost: false,
+        }
     }
+
+    /// The lifecycle events `message` stands for, if any.
+    fn observe(&mut self, message: &ServerMessage) -> Vec<Lifecycle> {
+        let source = message.source();
+        let connection = match connection_id(source) {
+            Some(connection) => connection,
+            None => return vec![],
+        };
+
+        match message {
+            // The welcome is the first message of every connection, and names the nick it
+            // logged in as.
+            ServerMessage::Generic(_) if source.command == "001" => {
+                let nick = source.params.first().cloned().unwrap_or_default();
+                self.nicks.insert(connection, nick);
+                let kind = match std::mem::take(&mut self.lost) {
+                    true => LifecycleKind::Reconnected,
+                    false => LifecycleKind::Connected,
+                };
+                self.handle
+                    .channels()
+                    .into_iter()
+                    .map(|channel| Lifecycle::new(channel, kind.clone()))
+                    .collect()
+            }
+            ServerMessage::Generic(_) if source.command == DISCONNECTED_COMMAND => {
+                let reason = source.params.first().cloned().unwrap_or_default();
+                self.close(connection, &reason)
+            }
+            ServerMessage::Reconnect(_) => self.close(connection, "reconnect requested by Twitch"),
+            ServerMessage::Join(join) if self.is_own(connection, &join.user_login) => {
+                let channel = join.channel_login.clone();
+                let mut events = vec![];
+                // A connection that died without its end being read is only noticed once its
+                // channels are joined again on another one.
+                if let Some(previous) = self.channels.insert(channel.clone(), connection) {
+                    if previous != connection {
+                        self.nicks.remove(&previous);
+                        events.push(Lifecycle::new(
+                            channel.clone(),
+                            LifecycleKind::Disconnected {
+                                reason: "connection lost".to_string(),
+                            },
+                        ));
+                    }
+                }
+                events.push(Lifecycle::new(channel, LifecycleKind::Joined));
+                events
+            }
+            ServerMessage::Part(part) if self.is_own(connection, &part.user_login) => {
+                self.channels.remove(&part.channel_login);
+                vec![Lifecycle::new(
+                    part.channel_login.clone(),
+                    LifecycleKind::Parted,
+                )]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn is_own(&self, connection: u64, login: &str) -> bool {
+        self.nicks
+            .get(&connection)
+            .is_some_and(|nick| nick.eq_ignore_ascii_case(login))
+    }
+
+    /// Forgets `connection`. Returns a `Disconnected` event for each channel joined on it, or
+    /// none if it was already closed.
+    fn close(&mut self, connection: u64, reason: &str) -> Vec<Lifecycle> {
+        if self.nicks.remove(&connection).is_none() {
+            return vec![];
+        }
+        self.lost = true;
+
+        let channels = self
+            .channels
+            .iter()
+            .filter(|(_, joined_on)| **joined_on == connection)
+            .map(|(channel, _)| channel.clone())
+            .collect::<BTreeSet<_>>();
+        channels
+            .into_iter()
+            .map(|channel| {
+                self.channels.remove(&channel);
+                Lifecycle::new(
+                    channel,
+                    LifecycleKind::Disconnected {
+                        reason: reason.to_string(),
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// Normalizes `#Channel` to the `channel` login Twitch expects.
+fn channel_login(channel: &str) -> Result<String, Error> {
+    let login = channel.trim().trim_start_matches('#').to_lowercase();
+    validate_login(&login).map_err(|e| Error::FailedToParse {
+        key: "channel login".to_string(),
+        value: channel.to_string(),
+        error: Some(e.to_string()),
+    })?;
+    Ok(login)
 }
@@ -1,12 +1,22 @@
-use config::Config as BaseConfig;
-use std::collections::HashMap;
+use crate::entities::chat::BadgeLevel;
+use crate::entities::event::Event;
+use crate::error::Error;
+use crate::filter::{FilterConfig, Pipeline};
+use crate::queue::OverflowPolicy;
+use crate::utils::chat_message_format::ChatMessageFormat;
+use crate::utils::table_name::TableName;
+use config::{Config as BaseConfig, ConfigError};
+use log::Level;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
 
 use dirs::config_dir;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub env_prefix: Option<String>,
     pub env_file: Option<PathBuf>,
@@ -15,21 +25,106 @@ pub struct Config {
     pub log_level: Option<String>,
     pub db_url: Option<String>,
     pub db_table: Option<String>,
+    pub store_raw: Option<bool>,
+    pub credentials: Option<CredentialsMode>,
+    pub admin_address: Option<SocketAddr>,
+    pub spool_file: Option<PathBuf>,
+    pub auto_migrate: Option<bool>,
+    pub sinks: Option<BTreeMap<String, SinkConfig>>,
+    pub filters: Option<Vec<FilterConfig>>,
+    pub shutdown_timeout: Option<u64>,
+    pub queue_capacity: Option<usize>,
+    pub overflow: Option<OverflowPolicy>,
+    pub overflow_file: Option<PathBuf>,
 }
 
 impl Config {
-    pub fn new(config_path: Option<String>) -> Self {
+    pub fn new(config_path: Option<String>) -> Result<Self, Error> {
         let config = Self::load(config_path);
 
-        Self {
+        Ok(Self {
             env_prefix: config.get("env_prefix").unwrap_or_default(),
             env_file: config.get("env_file").unwrap_or_default(),
-            channels: config.get("channel").unwrap_or_default(),
+            channels: section(&config, "channel")?,
             log_file: config.get("log_file").unwrap_or_default(),
             log_level: config.get("log_level").unwrap_or_default(),
             db_url: config.get("db_url").unwrap_or_default(),
             db_table: config.get("db_table").unwrap_or_default(),
+            store_raw: config.get("store_raw").unwrap_or_default(),
+            credentials: config.get("credentials").unwrap_or_default(),
+            admin_address: config.get("admin_address").unwrap_or_default(),
+            spool_file: config.get("spool_file").unwrap_or_default(),
+            auto_migrate: config.get("auto_migrate").unwrap_or_default(),
+            sinks: section(&config, "sink")?,
+            filters: section(&config, "filter")?,
+            shutdown_timeout: config.get("shutdown_timeout").unwrap_or_default(),
+            queue_capacity: config.get("queue_capacity").unwrap_or_default(),
+            overflow: config.get("overflow").unwrap_or_default(),
+            overflow_file: config.get("overflow_file").unwrap_or_default(),
+        })
+    }
+
+    /// Checks the values that would otherwise only fail once they are used.
+    pub fn validate(&self) -> Result<(), Error> {
+        self.table_name()?;
+        Pipeline::try_from(self)?;
+        if let Some(address) = self
+            .admin_address
+            .filter(|address| !address.ip().is_loopback())
+        {
+            return Err(Error::InvalidConfig(format!(
+                "admin_address: {} is not a loopback address, and the admin API has no \
+                 authentication",
+                address
+            )));
+        }
+        if self.queue_capacity == Some(0) {
+            return Err(Error::InvalidConfig(
+                "queue_capacity: must be at least 1".to_string(),
+            ));
+        }
+
+        let sinks = self.sinks();
+        for (name, sink) in &sinks {
+            if sink.batch.max_batch_size == Some(0) {
+                return Err(Error::InvalidConfig(format!(
+                    "sink.{}.max_batch_size: must be at least 1",
+                    name
+                )));
+            }
+        }
+        for (channel, channel_config) in self.channels.iter().flatten() {
+            for sink in channel_config.sinks.iter().flatten() {
+                if !sinks.contains_key(sink) {
+                    return Err(Error::InvalidConfig(format!(
+                        "channel.{}.sinks: no sink is named {:?}",
+                        channel, sink
+                    )));
+                }
+            }
         }
+        Ok(())
+    }
+
+    /// The configured sinks by name, or just the database if none are.
+    pub fn sinks(&self) -> BTreeMap<String, SinkConfig> {
+        self.sinks.clone().unwrap_or_else(|| {
+            BTreeMap::from([("database".to_string(), SinkConfig::new(SinkKind::Database))])
+        })
+    }
+
+    /// How long to wait for buffered events to be written after a shutdown signal, by default
+    /// 10 seconds.
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout.unwrap_or(10))
+    }
+
+    pub fn table_name(&self) -> Result<TableName, Error> {
+        let name = self
+            .db_table
+            .as_deref()
+            .ok_or_else(|| Error::MissingConfig("db_table".to_string()))?;
+        TableName::parse(name)
     }
 
     fn load(config_path: Option<String>) -> BaseConfig {
@@ -51,5 +146,127 @@ impl Config {
     }
 }
 
+/// Reads the table or array `key`: `None` if it is absent, and an error if it is malformed.
+fn section<'de, T: Deserialize<'de>>(config: &BaseConfig, key: &str) -> Result<Option<T>, Error> {
+    match config.get(key) {
+        Ok(value) => Ok(Some(value)),
+        Err(ConfigError::NotFound(_)) => Ok(None),
+        Err(e) => Err(Error::InvalidConfig(format!("{}: {}", key, e))),
+    }
+}
+
+/// Settings for one channel, under `[channel.<name>]`. Channels without settings, such as ones
+/// joined through the admin API, log everything to every sink.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+pub struct ChannelConfig {
+    /// The sinks this channel is written to. All of them if unset.
+    pub sinks: Option<Vec<String>>,
+    /// Users whose chat messages are not logged, e.g. `nightbot`.
+    pub ignored_users: Option<Vec<String>>,
+    /// Whether chat messages starting with `!` are logged. Defaults to true.
+    pub log_commands: Option<bool>,
+    /// Chat messages from users below this level are not logged.
+    pub min_badge_level: Option<BadgeLevel>,
+    /// Whether notices, moderation, room state and presence are logged. Defaults to true.
+    pub non_chat_events: Option<bool>,
+}
+
+impl ChannelConfig {
+    /// Whether `event` should be logged at all. Gaps and lifecycle events always are.
+    pub fn accepts(&self, event: &Event) -> bool {
+        let message = match event {
+            Event::Chat(message) => message,
+            Event::Gap(_) | Event::Lifecycle(_) => return true,
+            _ => return self.non_chat_events.unwrap_or(true),
+        };
+
+        let ignored = self
+            .ignored_users
+            .iter()
+            .flatten()
+            .any(|user| user.eq_ignore_ascii_case(&message.username));
+        !ignored
+            && (self.log_commands.unwrap_or(true) || !message.is_command())
+            && message.badge_level() >= self.min_badge_level.unwrap_or_default()
+    }
+
+    /// Whether events from this channel are written to the sink called `sink`.
+    pub fn routes_to(&self, sink: &str) -> bool {
+        match &self.sinks {
+            Some(sinks) => sinks.iter().any(|name| name == sink),
+            None => true,
+        }
+    }
+}
+
+/// An output events are written to, configured as `[sink.<name>]` with a `type` and optional
+/// batch settings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SinkConfig {
+    #[serde(flatten)]
+    pub kind: SinkKind,
+    #[serde(flatten)]
+    pub batch: BatchConfig,
+}
+
+impl SinkConfig {
+    pub fn new(kind: SinkKind) -> Self {
+        Self {
+            kind,
+            batch: BatchConfig::default(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct ChannelConfig {}
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkKind {
+    /// The database at `db_url`.
+    Database,
+    /// Appends one line per event to `path`.
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        format: ChatMessageFormat,
+    },
+    /// Writes one line per event to the application log.
+    Console {
+        #[serde(default = "default_console_level")]
+        level: Level,
+        #[serde(default = "default_console_format")]
+        format: ChatMessageFormat,
+    },
+}
+
+/// When a sink writes the events buffered for it. A batch is written as soon as any limit is
+/// reached; see `BatchPolicy` for the defaults.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy)]
+pub struct BatchConfig {
+    /// The most events written at once.
+    pub max_batch_size: Option<usize>,
+    /// The longest an event is buffered, in milliseconds.
+    pub max_batch_age_ms: Option<u64>,
+    /// The most bytes buffered, counting events as JSON.
+    pub max_batch_bytes: Option<usize>,
+}
+
+fn default_console_level() -> Level {
+    Level::Info
+}
+
+fn default_console_format() -> ChatMessageFormat {
+    ChatMessageFormat::Simple
+}
+
+/// How the client logs in to Twitch chat.
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialsMode {
+    /// Read-only `justinfan` login, which needs no account or app registration.
+    Anonymous,
+    /// A fixed OAuth token for `USERNAME`, read from `OAUTH_TOKEN`.
+    Static,
+    /// User tokens stored in the env file and refreshed with `CLIENT_ID` and `CLIENT_SECRET`.
+    #[default]
+    Refreshing,
+}
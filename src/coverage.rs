@@ -0,0 +1,388 @@
+use crate::entities::gap::Gap;
+use crate::entities::lifecycle::{Lifecycle, LifecycleKind};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// What the database holds about how completely each channel was logged.
+#[derive(Debug, Default, Clone)]
+pub struct CoverageRecords {
+    /// Chat messages by channel and minute, as `(channel, minute, count)`.
+    pub activity: Vec<(String, DateTime<Utc>, u64)>,
+    pub lifecycle: Vec<Lifecycle>,
+    pub gaps: Vec<Gap>,
+}
+
+/// The thresholds `coverage` works with.
+#[derive(Debug, Clone, Copy)]
+pub struct CoverageOptions {
+    /// Before a channel has lifecycle events, messages further apart than this are taken to be
+    /// in separate intervals.
+    pub max_silence: Duration,
+    /// The shortest silence inside an interval that can be a hole.
+    pub min_hole: Duration,
+    /// How long before and after a silence the message rate is measured.
+    pub window: Duration,
+    /// The rate, in messages per minute, at which a channel is taken to be live.
+    pub live_rate: f64,
+}
+
+impl Default for CoverageOptions {
+    fn default() -> Self {
+        Self {
+            max_silence: Duration::minutes(30),
+            min_hole: Duration::minutes(5),
+            window: Duration::minutes(10),
+            live_rate: 1.0,
+        }
+    }
+}
+
+/// When the logger was in a channel, by day.
+#[derive(Debug, Clone)]
+pub struct ChannelCoverage {
+    pub channel: String,
+    pub days: Vec<DayCoverage>,
+}
+
+/// One UTC day of a `ChannelCoverage`.
+#[derive(Debug, Clone)]
+pub struct DayCoverage {
+    pub date: NaiveDate,
+    pub intervals: Vec<Interval>,
+    pub holes: Vec<Hole>,
+}
+
+/// A period the logger was connected to a channel and receiving its events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Whether it was worked out from messages alone, before lifecycle events were logged.
+    pub inferred: bool,
+}
+
+/// A period inside an `Interval` whose events are likely missing.
+#[derive(Debug, Clone)]
+pub struct Hole {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub reason: HoleReason,
+}
+
+#[derive(Debug, Clone)]
+pub enum HoleReason {
+    /// No messages, between two periods in which the channel was live.
+    Silent { rate_before: f64, rate_after: f64 },
+    /// The logger dropped events because it could not keep up.
+    Dropped { events: u64 },
+}
+
+/// Works out, for every channel in `records`, when the logger was in it and where its logs
+/// likely have holes.
+///
+/// Intervals run from each `joined` to the next `disconnected`, `parted` or `stopped`. A run
+/// that ended without `stopped` is taken to have lasted until its last message. Messages older
+/// than a channel's first lifecycle event are grouped into intervals by `max_silence`.
+///
+/// Twitch chat does not say whether a stream is live, so a silence is flagged when the channel
+/// had at least `live_rate` messages per minute both before and after it.
+pub fn coverage(records: &CoverageRecords, options: &CoverageOptions) -> Vec<ChannelCoverage> {
+    let mut activity = BTreeMap::<&str, BTreeMap<DateTime<Utc>, u64>>::new();
+    for (channel, minute, count) in &records.activity {
+        *activity
+            .entry(channel)
+            .or_default()
+            .entry(*minute)
+            .or_default() += count;
+    }
+    let mut lifecycle = BTreeMap::<&str, Vec<&Lifecycle>>::new();
+    for record in &records.lifecycle {
+        lifecycle.entry(&record.channel).or_default().push(record);
+    }
+    let mut gaps = BTreeMap::<&str, Vec<&Gap>>::new();
+    for gap in &records.gaps {
+        gaps.entry(&gap.channel).or_default().push(gap);
+    }
+
+    let mut channels = activity.keys().copied().collect::<Vec<_>>();
+    channels.extend(lifecycle.keys());
+    channels.extend(gaps.keys());
+    channels.sort_unstable();
+    channels.dedup();
+
+    let empty = BTreeMap::new();
+    channels
+        .into_iter()
+        .map(|channel| {
+            let activity = activity.get(channel).unwrap_or(&empty);
+            let mut lifecycle = lifecycle.remove(channel).unwrap_or_default();
+            lifecycle.sort_by_key(|record| record.recorded_at);
+
+            let intervals = intervals(activity, &lifecycle, options);
+            let mut holes = silences(activity, &intervals, options);
+            holes.extend(gaps.get(channel).into_iter().flatten().map(|gap| Hole {
+                start: gap.started_at,
+                end: gap.ended_at,
+                reason: HoleReason::Dropped {
+                    events: gap.dropped,
+                },
+            }));
+
+            ChannelCoverage {
+                channel: channel.to_string(),
+                days: by_day(intervals, holes),
+            }
+        })
+        .collect()
+}
+
+fn intervals(
+    activity: &BTreeMap<DateTime<Utc>, u64>,
+    lifecycle: &[&Lifecycle],
+    options: &CoverageOptions,
+) -> Vec<Interval> {
+    let first_record = lifecycle.first().map(|record| record.recorded_at);
+    let mut intervals = vec![];
+
+    let mut inferred: Option<Interval> = None;
+    for minute in activity.keys() {
+        if first_record.is_some_and(|first| *minute >= first) {
+            break;
+        }
+        let end = *minute + Duration::minutes(1);
+        match &mut inferred {
+            Some(interval) if *minute - interval.end <= options.max_silence => interval.end = end,
+            _ => {
+                intervals.extend(inferred.take());
+                inferred = Some(Interval {
+                    start: *minute,
+                    end,
+                    inferred: true,
+                });
+            }
+        }
+    }
+    intervals.extend(inferred);
+
+    let last_message_before = |start: DateTime<Utc>, end: Option<DateTime<Utc>>| {
+        let minutes = match end {
+            Some(end) => activity.range(start..end),
+            None => activity.range(start..),
+        };
+        minutes.last().map_or(start, |(minute, _)| {
+            let end_of_minute = *minute + Duration::minutes(1);
+            end.map_or(end_of_minute, |end| end_of_minute.min(end))
+        })
+    };
+
+    let mut joined_at = None;
+    for record in lifecycle {
+        let end = match &record.event {
+            LifecycleKind::Joined => {
+                joined_at = joined_at.or(Some(record.recorded_at));
+                continue;
+            }
+            LifecycleKind::Disconnected { .. }
+            | LifecycleKind::Parted
+            | LifecycleKind::Stopped { .. } => record.recorded_at,
+            // A run that started while the last one was still in the channel ended without
+            // stopping.
+            LifecycleKind::Started { .. } => match joined_at {
+                Some(start) => last_message_before(start, Some(record.recorded_at)),
+                None => continue,
+            },
+            LifecycleKind::Connected | LifecycleKind::Reconnected => continue,
+        };
+        if let Some(start) = joined_at.take() {
+            intervals.push(Interval {
+                start,
+                end,
+                inferred: false,
+            });
+        }
+    }
+    if let Some(start) = joined_at {
+        intervals.push(Interval {
+            start,
+            end: last_message_before(start, None),
+            inferred: false,
+        });
+    }
+
+    intervals
+}
+
+/// The silences inside `intervals` during which the channel was likely live.
+fn silences(
+    activity: &BTreeMap<DateTime<Utc>, u64>,
+    intervals: &[Interval],
+    options: &CoverageOptions,
+) -> Vec<Hole> {
+    let rate = |from: DateTime<Utc>, to: DateTime<Utc>| {
+        let messages = activity
+            .range(from..to)
+            .map(|(_, count)| count)
+            .sum::<u64>();
+        messages as f64 / options.window.num_minutes().max(1) as f64
+    };
+
+    let mut holes = vec![];
+    for interval in intervals {
+        let minutes = activity
+            .range(interval.start..interval.end)
+            .map(|(minute, _)| *minute)
+            .collect::<Vec<_>>();
+        for pair in minutes.windows(2) {
+            let start = pair[0] + Duration::minutes(1);
+            let end = pair[1];
+            if end - start < options.min_hole {
+                continue;
+            }
+
+            let rate_before = rate(start - options.window, start);
+            let rate_after = rate(end, end + options.window);
+            if rate_before >= options.live_rate && rate_after >= options.live_rate {
+                holes.push(Hole {
+                    start,
+                    end,
+                    reason: HoleReason::Silent {
+                        rate_before,
+                        rate_after,
+                    },
+                });
+            }
+        }
+    }
+    holes
+}
+
+/// Splits `intervals` at midnight and groups them by the UTC day they fall on. Holes are not
+/// split, so that dropped events are counted once, and go to the day they start on.
+fn by_day(intervals: Vec<Interval>, holes: Vec<Hole>) -> Vec<DayCoverage> {
+    let mut days = BTreeMap::<NaiveDate, DayCoverage>::new();
+
+    for interval in intervals {
+        let mut start = interval.start;
+        loop {
+            let date = start.date_naive();
+            let midnight = start_of(date + Duration::days(1));
+            let end = interval.end.min(midnight);
+            day(&mut days, date).intervals.push(Interval {
+                start,
+                end,
+                inferred: interval.inferred,
+            });
+            if end >= interval.end {
+                break;
+            }
+            start = end;
+        }
+    }
+    for hole in holes {
+        day(&mut days, hole.start.date_naive()).holes.push(hole);
+    }
+
+    let mut days = days.into_values().collect::<Vec<_>>();
+    for day in &mut days {
+        day.intervals.sort_by_key(|interval| interval.start);
+        day.holes.sort_by_key(|hole| hole.start);
+    }
+    days
+}
+
+fn day(days: &mut BTreeMap<NaiveDate, DayCoverage>, date: NaiveDate) -> &mut DayCoverage {
+    days.entry(date).or_insert_with(|| DayCoverage {
+        date,
+        intervals: vec![],
+        holes: vec![],
+    })
+}
+
+fn start_of(date: NaiveDate) -> DateTime<Utc> {
+    Utc.from_utc_datetime(&date.and_time(NaiveTime::MIN))
+}
+
+impl DayCoverage {
+    /// How long the logger was in the channel on this day.
+    pub fn covered(&self) -> Duration {
+        self.intervals
+            .iter()
+            .fold(Duration::zero(), |total, interval| {
+                total + (interval.end - interval.start)
+            })
+    }
+}
+
+impl Display for ChannelCoverage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "#{}", self.channel)?;
+        for day in &self.days {
+            let intervals = day
+                .intervals
+                .iter()
+                .map(|interval| {
+                    format!(
+                        "{}-{}{}",
+                        time_of_day(interval.start, day.date),
+                        time_of_day(interval.end, day.date),
+                        if interval.inferred { "*" } else { "" }
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(
+                f,
+                "  {}  {}  {}",
+                day.date,
+                format_duration(day.covered()),
+                intervals.join(", ")
+            )?;
+            for hole in &day.holes {
+                writeln!(
+                    f,
+                    "    ! {}-{}  {}",
+                    time_of_day(hole.start, day.date),
+                    time_of_day(hole.end, day.date),
+                    hole.reason
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for HoleReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HoleReason::Silent {
+                rate_before,
+                rate_after,
+            } => write!(
+                f,
+                "no messages while live ({:.1}/min before, {:.1}/min after)",
+                rate_before, rate_after
+            ),
+            HoleReason::Dropped { events } => write!(f, "{} events dropped", events),
+        }
+    }
+}
+
+/// `at` as a time of `date`, where the midnight that ends it is `24:00:00`. Later instants, such
+/// as the end of a hole that runs past midnight, are given with their date.
+fn time_of_day(at: DateTime<Utc>, date: NaiveDate) -> String {
+    if at == start_of(date + Duration::days(1)) {
+        return "24:00:00".to_string();
+    }
+    if at.date_naive() > date {
+        return at.format("%Y-%m-%d %H:%M:%S").to_string();
+    }
+    at.format("%H:%M:%S").to_string()
+}
+
+fn format_duration(duration: Duration) -> String {
+    format!(
+        "{:>2}h {:02}m",
+        duration.num_hours(),
+        duration.num_minutes() % 60
+    )
+}
@@ -0,0 +1,76 @@
+use crate::config::{Config, CredentialsMode};
+use crate::error::Error;
+use crate::utils::env::EnvStorage;
+use async_trait::async_trait;
+use twitch_irc::login::{
+    CredentialsPair, LoginCredentials, RefreshingLoginCredentials, StaticLoginCredentials,
+};
+
+/// The credentials `Client` logs in with, as selected by the `credentials` config option.
+#[derive(Debug, Clone)]
+pub enum Credentials {
+    /// An anonymous `justinfan` login or a fixed OAuth token.
+    Static(StaticLoginCredentials),
+    /// A user token that is refreshed through the app's client id and secret.
+    Refreshing(RefreshingLoginCredentials<EnvStorage>),
+}
+
+impl Credentials {
+    pub fn anonymous() -> Self {
+        Self::Static(StaticLoginCredentials::anonymous())
+    }
+
+    /// Logs in as `USERNAME` with the token in `OAUTH_TOKEN`.
+    pub fn from_token(env: &EnvStorage) -> Result<Self, Error> {
+        let username: String = env.get_env("USERNAME")?;
+        let token: String = env.get_env("OAUTH_TOKEN")?;
+        let token = token.trim_start_matches("oauth:").to_string();
+
+        Ok(Self::Static(StaticLoginCredentials::new(
+            username,
+            Some(token),
+        )))
+    }
+
+    /// Logs in with the tokens in the env file, refreshing them with `CLIENT_ID` and
+    /// `CLIENT_SECRET`.
+    pub fn refreshing(env: EnvStorage) -> Result<Self, Error> {
+        let username: Option<String> = env
+            .get_env_opt("USERNAME")?
+            .filter(|s: &String| !s.is_empty());
+
+        let client_id = env.get_env("CLIENT_ID")?;
+        let client_secret = env.get_env("CLIENT_SECRET")?;
+
+        Ok(Self::Refreshing(
+            RefreshingLoginCredentials::init_with_username(username, client_id, client_secret, env),
+        ))
+    }
+}
+
+#[async_trait]
+impl LoginCredentials for Credentials {
+    type Error = Error;
+
+    async fn get_credentials(&self) -> Result<CredentialsPair, Self::Error> {
+        match self {
+            Credentials::Static(credentials) => Ok(credentials.credentials.clone()),
+            Credentials::Refreshing(credentials) => credentials
+                .get_credentials()
+                .await
+                .map_err(|e| Error::Other(format!("Failed to refresh token: {}", e))),
+        }
+    }
+}
+
+impl TryFrom<&Config> for Credentials {
+    type Error = Error;
+
+    fn try_from(config: &Config) -> Result<Self, Self::Error> {
+        match config.credentials.unwrap_or_default() {
+            CredentialsMode::Anonymous => Ok(Self::anonymous()),
+            CredentialsMode::Static => Self::from_token(&EnvStorage::from(config)),
+            CredentialsMode::Refreshing => Self::refreshing(EnvStorage::from(config)),
+        }
+    }
+}
@@ -1,13 +1,76 @@
+use crate::entities::tag_value;
+use crate::transport::raw_line;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
+use twitch_irc::message::{Badge, Emote, IRCMessage, PrivmsgMessage};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub channel: String,
     pub username: String,
     pub message: String,
     pub sent_at: DateTime<Utc>,
+    #[serde(default)]
+    pub message_id: Option<String>,
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub badges: Vec<Badge>,
+    #[serde(default)]
+    pub badge_info: Vec<Badge>,
+    #[serde(default)]
+    pub emotes: Vec<Emote>,
+    #[serde(default)]
+    pub bits: Option<u64>,
+    #[serde(default)]
+    pub reply_parent: Option<ReplyParent>,
+    #[serde(default)]
+    pub room_id: Option<String>,
+    /// Sent with `/me`. `message` is the text without the `ACTION` wrapper.
+    #[serde(default)]
+    pub is_action: bool,
+    /// The exact IRC line this was parsed from, kept when `store_raw` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+/// The message a chat message was sent in reply to, taken from the `reply-parent-*` tags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplyParent {
+    pub message_id: String,
+    pub user_id: Option<String>,
+    pub user_login: Option<String>,
+    pub display_name: Option<String>,
+    pub message: Option<String>,
+}
+
+/// The highest rank a chatter's badges show in the channel, lowest first.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BadgeLevel {
+    #[default]
+    Everyone,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+impl BadgeLevel {
+    fn of_badge(badge: &Badge) -> Self {
+        match badge.name.as_str() {
+            "broadcaster" => BadgeLevel::Broadcaster,
+            "moderator" => BadgeLevel::Moderator,
+            "vip" => BadgeLevel::Vip,
+            "subscriber" | "founder" => BadgeLevel::Subscriber,
+            _ => BadgeLevel::Everyone,
+        }
+    }
 }
 
 impl Display for ChatMessage {
@@ -23,17 +86,59 @@ impl ChatMessage {
             username,
             message,
             sent_at,
+            ..Default::default()
         }
     }
+
+    pub fn badge_level(&self) -> BadgeLevel {
+        self.badges
+            .iter()
+            .map(BadgeLevel::of_badge)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Whether the message is a bot command such as `!uptime`.
+    pub fn is_command(&self) -> bool {
+        self.message.starts_with('!')
+    }
 }
 
-impl From<twitch_irc::message::PrivmsgMessage> for ChatMessage {
-    fn from(message: twitch_irc::message::PrivmsgMessage) -> Self {
-        Self::new(
-            message.channel_login,
-            message.sender.login,
-            message.message_text,
-            message.server_timestamp,
-        )
+impl ReplyParent {
+    fn from_tags(source: &IRCMessage) -> Option<Self> {
+        let tag = |key: &str| tag_value(source, key);
+
+        Some(Self {
+            message_id: tag("reply-parent-msg-id")?,
+            user_id: tag("reply-parent-user-id"),
+            user_login: tag("reply-parent-user-login"),
+            display_name: tag("reply-parent-display-name"),
+            message: tag("reply-parent-msg-body"),
+        })
+    }
+}
+
+impl From<PrivmsgMessage> for ChatMessage {
+    fn from(message: PrivmsgMessage) -> Self {
+        let reply_parent = ReplyParent::from_tags(&message.source);
+
+        Self {
+            channel: message.channel_login,
+            username: message.sender.login,
+            message: message.message_text,
+            sent_at: message.server_timestamp,
+            message_id: Some(message.message_id),
+            user_id: Some(message.sender.id),
+            display_name: Some(message.sender.name),
+            color: message.name_color.map(|color| color.to_string()),
+            badges: message.badges,
+            badge_info: message.badge_info,
+            emotes: message.emotes,
+            bits: message.bits,
+            reply_parent,
+            room_id: Some(message.channel_id),
+            is_action: message.is_action,
+            raw: Some(raw_line(&message.source)),
+        }
     }
 }
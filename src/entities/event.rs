@@ -0,0 +1,157 @@
+use crate::entities::chat::ChatMessage;
+use crate::entities::gap::Gap;
+use crate::entities::lifecycle::Lifecycle;
+use crate::entities::moderation::ModerationEvent;
+use crate::entities::presence::Presence;
+use crate::entities::room_state::RoomState;
+use crate::entities::user_notice::UserNotice;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use twitch_irc::message::ServerMessage;
+
+/// Everything the logger records, as passed from `Client` to `MessageHandler` and the sinks.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    Chat(ChatMessage),
+    UserNotice(UserNotice),
+    Moderation(ModerationEvent),
+    RoomState(RoomState),
+    Presence(Presence),
+    /// Added by the logger when it dropped events; see `Gap`.
+    Gap(Gap),
+    /// Added by the logger when it starts, stops, connects or joins; see `Lifecycle`.
+    Lifecycle(Lifecycle),
+}
+
+impl Event {
+    pub fn from_server_message(message: ServerMessage) -> Option<Self> {
+        match message {
+            ServerMessage::Privmsg(msg) => Some(Event::Chat(ChatMessage::from(msg))),
+            ServerMessage::UserNotice(msg) => Some(Event::UserNotice(UserNotice::from(msg))),
+            ServerMessage::ClearChat(msg) => Some(Event::Moderation(ModerationEvent::from(msg))),
+            ServerMessage::ClearMsg(msg) => Some(Event::Moderation(ModerationEvent::from(msg))),
+            ServerMessage::RoomState(msg) => Some(Event::RoomState(RoomState::from(msg))),
+            ServerMessage::Join(msg) => Some(Event::Presence(Presence::from(msg))),
+            ServerMessage::Part(msg) => Some(Event::Presence(Presence::from(msg))),
+            _ => None,
+        }
+    }
+
+    pub fn channel(&self) -> &str {
+        match self {
+            Event::Chat(message) => &message.channel,
+            Event::UserNotice(notice) => &notice.channel,
+            Event::Moderation(event) => &event.channel,
+            Event::RoomState(state) => &state.channel,
+            Event::Presence(presence) => &presence.channel,
+            Event::Gap(gap) => &gap.channel,
+            Event::Lifecycle(lifecycle) => &lifecycle.channel,
+        }
+    }
+
+    pub fn raw(&self) -> Option<&str> {
+        match self {
+            Event::Chat(message) => message.raw.as_deref(),
+            Event::UserNotice(notice) => notice.raw.as_deref(),
+            Event::Moderation(event) => event.raw.as_deref(),
+            Event::RoomState(state) => state.raw.as_deref(),
+            Event::Presence(presence) => presence.raw.as_deref(),
+            Event::Gap(_) | Event::Lifecycle(_) => None,
+        }
+    }
+
+    pub fn clear_raw(&mut self) {
+        match self {
+            Event::Chat(message) => message.raw = None,
+            Event::UserNotice(notice) => notice.raw = None,
+            Event::Moderation(event) => event.raw = None,
+            Event::RoomState(state) => state.raw = None,
+            Event::Presence(presence) => presence.raw = None,
+            Event::Gap(_) | Event::Lifecycle(_) => {}
+        }
+    }
+
+    /// Sets the time of events Twitch sends without a server timestamp, which otherwise default
+    /// to the moment they were parsed.
+    pub fn set_received_at(&mut self, received_at: DateTime<Utc>) {
+        match self {
+            Event::RoomState(state) => state.recorded_at = received_at,
+            Event::Presence(presence) => presence.recorded_at = received_at,
+            Event::Chat(_)
+            | Event::UserNotice(_)
+            | Event::Moderation(_)
+            | Event::Gap(_)
+            | Event::Lifecycle(_) => {}
+        }
+    }
+
+    pub fn sent_at(&self) -> DateTime<Utc> {
+        match self {
+            Event::Chat(message) => message.sent_at,
+            Event::UserNotice(notice) => notice.sent_at,
+            Event::Moderation(event) => event.sent_at,
+            Event::RoomState(state) => state.recorded_at,
+            Event::Presence(presence) => presence.recorded_at,
+            Event::Gap(gap) => gap.started_at,
+            Event::Lifecycle(lifecycle) => lifecycle.recorded_at,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Chat(message) => message.fmt(f),
+            Event::UserNotice(notice) => notice.fmt(f),
+            Event::Moderation(event) => event.fmt(f),
+            Event::RoomState(state) => state.fmt(f),
+            Event::Presence(presence) => presence.fmt(f),
+            Event::Gap(gap) => gap.fmt(f),
+            Event::Lifecycle(lifecycle) => lifecycle.fmt(f),
+        }
+    }
+}
+
+impl From<ChatMessage> for Event {
+    fn from(message: ChatMessage) -> Self {
+        Event::Chat(message)
+    }
+}
+
+impl From<UserNotice> for Event {
+    fn from(notice: UserNotice) -> Self {
+        Event::UserNotice(notice)
+    }
+}
+
+impl From<ModerationEvent> for Event {
+    fn from(event: ModerationEvent) -> Self {
+        Event::Moderation(event)
+    }
+}
+
+impl From<RoomState> for Event {
+    fn from(state: RoomState) -> Self {
+        Event::RoomState(state)
+    }
+}
+
+impl From<Presence> for Event {
+    fn from(presence: Presence) -> Self {
+        Event::Presence(presence)
+    }
+}
+
+impl From<Gap> for Event {
+    fn from(gap: Gap) -> Self {
+        Event::Gap(gap)
+    }
+}
+
+impl From<Lifecycle> for Event {
+    fn from(lifecycle: Lifecycle) -> Self {
+        Event::Lifecycle(lifecycle)
+    }
+}
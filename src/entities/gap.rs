@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// Events of a channel that the logger received but dropped because the handler could not keep
+/// up, written in their place so that the log shows it is incomplete.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Gap {
+    pub channel: String,
+    pub dropped: u64,
+    /// When the first and the last of the dropped events were received.
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+}
+
+impl Gap {
+    pub fn new(channel: String, at: DateTime<Utc>) -> Self {
+        Self {
+            channel,
+            dropped: 0,
+            started_at: at,
+            ended_at: at,
+        }
+    }
+
+    /// Counts one more dropped event, received at `at`.
+    pub fn record(&mut self, at: DateTime<Utc>) {
+        self.dropped += 1;
+        self.ended_at = self.ended_at.max(at);
+    }
+
+    /// Extends this gap by `other`, of the same channel.
+    pub fn merge(&mut self, other: Gap) {
+        self.dropped += other.dropped;
+        self.started_at = self.started_at.min(other.started_at);
+        self.ended_at = self.ended_at.max(other.ended_at);
+    }
+}
+
+impl Display for Gap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} events dropped from #{} between {} and {}",
+            self.dropped, self.channel, self.started_at, self.ended_at
+        )
+    }
+}
@@ -0,0 +1,114 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// A change in whether the logger was receiving a channel, written alongside its events so that
+/// the periods the logs cover can be worked out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Lifecycle {
+    pub channel: String,
+    pub event: LifecycleKind,
+    pub recorded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LifecycleKind {
+    /// The logger started, running `version`.
+    Started {
+        version: String,
+    },
+    /// The logger stopped, after writing everything it received.
+    Stopped {
+        version: String,
+    },
+    /// A connection to Twitch was opened.
+    Connected,
+    /// A connection to Twitch was opened after one was lost.
+    Reconnected,
+    /// The connection the channel was joined on was closed.
+    Disconnected {
+        reason: String,
+    },
+    /// Twitch confirmed the logger joined the channel; its events are received from now on.
+    Joined,
+    Parted,
+}
+
+impl Lifecycle {
+    pub fn new(channel: String, event: LifecycleKind) -> Self {
+        Self {
+            channel,
+            event,
+            recorded_at: Utc::now(),
+        }
+    }
+}
+
+impl LifecycleKind {
+    /// Rebuilds a kind from its `name` and fields, as stored in the database.
+    pub fn from_parts(name: &str, version: Option<String>, reason: Option<String>) -> Option<Self> {
+        let kind = match name {
+            "started" => LifecycleKind::Started {
+                version: version.unwrap_or_default(),
+            },
+            "stopped" => LifecycleKind::Stopped {
+                version: version.unwrap_or_default(),
+            },
+            "connected" => LifecycleKind::Connected,
+            "reconnected" => LifecycleKind::Reconnected,
+            "disconnected" => LifecycleKind::Disconnected {
+                reason: reason.unwrap_or_default(),
+            },
+            "joined" => LifecycleKind::Joined,
+            "parted" => LifecycleKind::Parted,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LifecycleKind::Started { .. } => "started",
+            LifecycleKind::Stopped { .. } => "stopped",
+            LifecycleKind::Connected => "connected",
+            LifecycleKind::Reconnected => "reconnected",
+            LifecycleKind::Disconnected { .. } => "disconnected",
+            LifecycleKind::Joined => "joined",
+            LifecycleKind::Parted => "parted",
+        }
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        match self {
+            LifecycleKind::Started { version } | LifecycleKind::Stopped { version } => {
+                Some(version)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            LifecycleKind::Disconnected { reason } => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl Display for LifecycleKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LifecycleKind::Started { version } => write!(f, "logger {} started", version),
+            LifecycleKind::Stopped { version } => write!(f, "logger {} stopped", version),
+            LifecycleKind::Disconnected { reason } => write!(f, "disconnected: {}", reason),
+            kind => f.write_str(kind.name()),
+        }
+    }
+}
+
+impl Display for Lifecycle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}: {}", self.channel, self.event)
+    }
+}
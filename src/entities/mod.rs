@@ -1 +1,21 @@
 pub mod chat;
+pub mod event;
+pub mod gap;
+pub mod lifecycle;
+pub mod moderation;
+pub mod presence;
+pub mod room_state;
+pub mod user_notice;
+
+use twitch_irc::message::IRCMessage;
+
+/// Returns the value of an IRCv3 tag, treating empty values as missing.
+pub(crate) fn tag_value(source: &IRCMessage, key: &str) -> Option<String> {
+    source
+        .tags
+        .0
+        .get(key)
+        .cloned()
+        .flatten()
+        .filter(|value| !value.is_empty())
+}
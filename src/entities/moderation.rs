@@ -0,0 +1,166 @@
+use crate::entities::tag_value;
+use crate::transport::raw_line;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use twitch_irc::message::{ClearChatAction, ClearChatMessage, ClearMsgMessage};
+
+/// A ban, timeout, chat clear or single message deletion, from `CLEARCHAT` and `CLEARMSG`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationEvent {
+    pub channel: String,
+    pub room_id: Option<String>,
+    pub action: ModerationAction,
+    pub sent_at: DateTime<Utc>,
+    /// The exact IRC line this was parsed from, kept when `store_raw` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ModerationAction {
+    ChatCleared,
+    Ban {
+        user_login: String,
+        user_id: String,
+    },
+    Timeout {
+        user_login: String,
+        user_id: String,
+        duration_seconds: u64,
+    },
+    MessageDeleted {
+        user_login: String,
+        message_id: String,
+        message: String,
+        is_action: bool,
+    },
+}
+
+impl ModerationAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ModerationAction::ChatCleared => "chat_cleared",
+            ModerationAction::Ban { .. } => "ban",
+            ModerationAction::Timeout { .. } => "timeout",
+            ModerationAction::MessageDeleted { .. } => "message_deleted",
+        }
+    }
+
+    pub fn target_login(&self) -> Option<&str> {
+        match self {
+            ModerationAction::ChatCleared => None,
+            ModerationAction::Ban { user_login, .. }
+            | ModerationAction::Timeout { user_login, .. }
+            | ModerationAction::MessageDeleted { user_login, .. } => Some(user_login),
+        }
+    }
+
+    /// `CLEARMSG` carries no user id, so deletions only know the target's login.
+    pub fn target_user_id(&self) -> Option<&str> {
+        match self {
+            ModerationAction::Ban { user_id, .. } | ModerationAction::Timeout { user_id, .. } => {
+                Some(user_id)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn target_message_id(&self) -> Option<&str> {
+        match self {
+            ModerationAction::MessageDeleted { message_id, .. } => Some(message_id),
+            _ => None,
+        }
+    }
+
+    pub fn duration_seconds(&self) -> Option<u64> {
+        match self {
+            ModerationAction::Timeout {
+                duration_seconds, ..
+            } => Some(*duration_seconds),
+            _ => None,
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            ModerationAction::MessageDeleted { message, .. } => Some(message),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ModerationAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModerationAction::ChatCleared => write!(f, "chat was cleared"),
+            ModerationAction::Ban { user_login, .. } => write!(f, "{} was banned", user_login),
+            ModerationAction::Timeout {
+                user_login,
+                duration_seconds,
+                ..
+            } => write!(f, "{} was timed out for {}s", user_login, duration_seconds),
+            ModerationAction::MessageDeleted {
+                user_login,
+                message,
+                ..
+            } => write!(f, "message from {} was deleted: {}", user_login, message),
+        }
+    }
+}
+
+impl Display for ModerationEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[#{}] {}", self.channel, self.action)
+    }
+}
+
+impl From<ClearChatMessage> for ModerationEvent {
+    fn from(message: ClearChatMessage) -> Self {
+        let action = match message.action {
+            ClearChatAction::ChatCleared => ModerationAction::ChatCleared,
+            ClearChatAction::UserBanned {
+                user_login,
+                user_id,
+            } => ModerationAction::Ban {
+                user_login,
+                user_id,
+            },
+            ClearChatAction::UserTimedOut {
+                user_login,
+                user_id,
+                timeout_length,
+            } => ModerationAction::Timeout {
+                user_login,
+                user_id,
+                duration_seconds: timeout_length.as_secs(),
+            },
+        };
+
+        Self {
+            channel: message.channel_login,
+            room_id: Some(message.channel_id),
+            action,
+            sent_at: message.server_timestamp,
+            raw: Some(raw_line(&message.source)),
+        }
+    }
+}
+
+impl From<ClearMsgMessage> for ModerationEvent {
+    fn from(message: ClearMsgMessage) -> Self {
+        Self {
+            room_id: tag_value(&message.source, "room-id"),
+            channel: message.channel_login,
+            action: ModerationAction::MessageDeleted {
+                user_login: message.sender_login,
+                message_id: message.message_id,
+                message: message.message_text,
+                is_action: message.is_action,
+            },
+            sent_at: message.server_timestamp,
+            raw: Some(raw_line(&message.source)),
+        }
+    }
+}
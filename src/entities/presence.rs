@@ -0,0 +1,80 @@
+use crate::transport::raw_line;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use twitch_irc::message::{JoinMessage, PartMessage};
+
+/// A user joining or leaving a channel, as reported through the `twitch.tv/membership`
+/// capability. Twitch batches these, so `recorded_at` can lag the real change by a few seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Presence {
+    pub channel: String,
+    pub username: String,
+    pub action: PresenceAction,
+    pub recorded_at: DateTime<Utc>,
+    /// The exact IRC line this was parsed from, kept when `store_raw` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresenceAction {
+    Join,
+    Part,
+}
+
+/// A stretch of time a user was in a channel. Sessions still open when the logger parts the
+/// channel, is disconnected from it or stops are closed then; those left open by a crash are closed
+/// on the next start, at the last moment the logger saw activity in the channel.
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PresenceSession {
+    pub channel: String,
+    pub username: String,
+    pub joined_at: DateTime<Utc>,
+    pub left_at: Option<DateTime<Utc>>,
+    pub close_reason: Option<String>,
+}
+
+impl PresenceAction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PresenceAction::Join => "join",
+            PresenceAction::Part => "part",
+        }
+    }
+}
+
+impl Display for Presence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let verb = match self.action {
+            PresenceAction::Join => "joined",
+            PresenceAction::Part => "left",
+        };
+        write!(f, "[#{}] {} {}", self.channel, self.username, verb)
+    }
+}
+
+impl From<JoinMessage> for Presence {
+    fn from(message: JoinMessage) -> Self {
+        Self {
+            channel: message.channel_login,
+            username: message.user_login,
+            action: PresenceAction::Join,
+            recorded_at: Utc::now(),
+            raw: Some(raw_line(&message.source)),
+        }
+    }
+}
+
+impl From<PartMessage> for Presence {
+    fn from(message: PartMessage) -> Self {
+        Self {
+            channel: message.channel_login,
+            username: message.user_login,
+            action: PresenceAction::Part,
+            recorded_at: Utc::now(),
+            raw: Some(raw_line(&message.source)),
+        }
+    }
+}
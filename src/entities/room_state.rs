@@ -0,0 +1,120 @@
+use crate::transport::raw_line;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use twitch_irc::message::{FollowersOnlyMode, RoomStateMessage};
+
+/// A `ROOMSTATE` update. The first one after joining carries every setting, later ones only the
+/// settings that changed; `None` means unchanged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomState {
+    pub channel: String,
+    pub room_id: String,
+    pub emote_only: Option<bool>,
+    pub followers_only: Option<FollowersOnly>,
+    pub slow_mode_seconds: Option<u64>,
+    pub subs_only: Option<bool>,
+    pub r9k: Option<bool>,
+    pub recorded_at: DateTime<Utc>,
+    /// The exact IRC line this was parsed from, kept when `store_raw` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FollowersOnly {
+    Disabled,
+    Enabled { minutes: u64 },
+}
+
+/// The effective settings of a channel, built by applying `RoomState` updates in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelState {
+    pub emote_only: Option<bool>,
+    pub followers_only: Option<FollowersOnly>,
+    pub slow_mode_seconds: Option<u64>,
+    pub subs_only: Option<bool>,
+    pub r9k: Option<bool>,
+}
+
+impl FollowersOnly {
+    /// Twitch's own encoding: `-1` when disabled, otherwise the minimum follow age in minutes.
+    pub fn to_minutes(self) -> i64 {
+        match self {
+            FollowersOnly::Disabled => -1,
+            FollowersOnly::Enabled { minutes } => minutes as i64,
+        }
+    }
+
+    pub fn from_minutes(minutes: i64) -> Self {
+        if minutes < 0 {
+            FollowersOnly::Disabled
+        } else {
+            FollowersOnly::Enabled {
+                minutes: minutes as u64,
+            }
+        }
+    }
+}
+
+impl ChannelState {
+    pub fn apply(&mut self, update: &RoomState) {
+        self.emote_only = update.emote_only.or(self.emote_only);
+        self.followers_only = update.followers_only.or(self.followers_only);
+        self.slow_mode_seconds = update.slow_mode_seconds.or(self.slow_mode_seconds);
+        self.subs_only = update.subs_only.or(self.subs_only);
+        self.r9k = update.r9k.or(self.r9k);
+    }
+}
+
+impl RoomState {
+    /// The changed settings in `ROOMSTATE` tag notation, e.g. `slow=30 subs-only=true`.
+    pub fn changes(&self) -> String {
+        let mut changes = vec![];
+        if let Some(emote_only) = self.emote_only {
+            changes.push(format!("emote-only={}", emote_only));
+        }
+        if let Some(followers_only) = self.followers_only {
+            changes.push(format!("followers-only={}", followers_only.to_minutes()));
+        }
+        if let Some(slow) = self.slow_mode_seconds {
+            changes.push(format!("slow={}", slow));
+        }
+        if let Some(subs_only) = self.subs_only {
+            changes.push(format!("subs-only={}", subs_only));
+        }
+        if let Some(r9k) = self.r9k {
+            changes.push(format!("r9k={}", r9k));
+        }
+
+        changes.join(" ")
+    }
+}
+
+impl Display for RoomState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[#{}] room state: {}", self.channel, self.changes())
+    }
+}
+
+impl From<RoomStateMessage> for RoomState {
+    fn from(message: RoomStateMessage) -> Self {
+        Self {
+            channel: message.channel_login,
+            room_id: message.channel_id,
+            emote_only: message.emote_only,
+            followers_only: message.follwers_only.map(|mode| match mode {
+                FollowersOnlyMode::Disabled => FollowersOnly::Disabled,
+                FollowersOnlyMode::Enabled(duration) => FollowersOnly::Enabled {
+                    minutes: duration.as_secs() / 60,
+                },
+            }),
+            slow_mode_seconds: message.slow_mode.map(|duration| duration.as_secs()),
+            subs_only: message.subscribers_only,
+            r9k: message.r9k,
+            recorded_at: Utc::now(),
+            raw: Some(raw_line(&message.source)),
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use crate::transport::raw_line;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use twitch_irc::message::{
+    Badge, Emote, IRCMessage, SubGiftPromo, TwitchUserBasics, UserNoticeEvent, UserNoticeMessage,
+};
+
+/// A `USERNOTICE` sent to a channel: subs, gift subs, raids, announcements and similar events.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserNotice {
+    pub channel: String,
+    pub room_id: String,
+    pub username: String,
+    pub user_id: String,
+    pub display_name: String,
+    pub message_id: String,
+    /// The raw `msg-id` tag, e.g. `resub` or `announcement`.
+    pub event_id: String,
+    pub event: UserNoticeKind,
+    pub system_message: String,
+    pub message: Option<String>,
+    pub color: Option<String>,
+    pub badges: Vec<Badge>,
+    pub badge_info: Vec<Badge>,
+    pub emotes: Vec<Emote>,
+    pub sent_at: DateTime<Utc>,
+    /// The exact IRC line this was parsed from, kept when `store_raw` is enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UserNoticeKind {
+    SubOrResub {
+        is_resub: bool,
+        cumulative_months: u64,
+        streak_months: Option<u64>,
+        sub_plan: String,
+        sub_plan_name: String,
+    },
+    Raid {
+        viewer_count: u64,
+        profile_image_url: String,
+    },
+    SubGift {
+        is_sender_anonymous: bool,
+        cumulative_months: u64,
+        recipient: TwitchUserBasics,
+        sub_plan: String,
+        sub_plan_name: String,
+        num_gifted_months: u64,
+    },
+    SubMysteryGift {
+        mass_gift_count: u64,
+        sender_total_gifts: u64,
+        sub_plan: String,
+    },
+    AnonSubMysteryGift {
+        mass_gift_count: u64,
+        sub_plan: String,
+    },
+    GiftPaidUpgrade {
+        gifter_login: String,
+        gifter_name: String,
+        promotion: Option<SubGiftPromo>,
+    },
+    AnonGiftPaidUpgrade {
+        promotion: Option<SubGiftPromo>,
+    },
+    Ritual {
+        ritual_name: String,
+    },
+    BitsBadgeTier {
+        threshold: u64,
+    },
+    Announcement {
+        color: Option<String>,
+    },
+    /// Any event twitch-irc does not parse, with its `msg-param-*` tags.
+    Other {
+        params: BTreeMap<String, String>,
+    },
+}
+
+impl Display for UserNotice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[#{}] * {}", self.channel, self.system_message)?;
+        if let Some(message) = &self.message {
+            write!(f, ": {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+impl UserNoticeKind {
+    fn from_event(event: UserNoticeEvent, event_id: &str, source: &IRCMessage) -> Self {
+        match event {
+            UserNoticeEvent::SubOrResub {
+                is_resub,
+                cumulative_months,
+                streak_months,
+                sub_plan,
+                sub_plan_name,
+            } => Self::SubOrResub {
+                is_resub,
+                cumulative_months,
+                streak_months,
+                sub_plan,
+                sub_plan_name,
+            },
+            UserNoticeEvent::Raid {
+                viewer_count,
+                profile_image_url,
+            } => Self::Raid {
+                viewer_count,
+                profile_image_url,
+            },
+            UserNoticeEvent::SubGift {
+                is_sender_anonymous,
+                cumulative_months,
+                recipient,
+                sub_plan,
+                sub_plan_name,
+                num_gifted_months,
+            } => Self::SubGift {
+                is_sender_anonymous,
+                cumulative_months,
+                recipient,
+                sub_plan,
+                sub_plan_name,
+                num_gifted_months,
+            },
+            UserNoticeEvent::SubMysteryGift {
+                mass_gift_count,
+                sender_total_gifts,
+                sub_plan,
+            } => Self::SubMysteryGift {
+                mass_gift_count,
+                sender_total_gifts,
+                sub_plan,
+            },
+            UserNoticeEvent::AnonSubMysteryGift {
+                mass_gift_count,
+                sub_plan,
+            } => Self::AnonSubMysteryGift {
+                mass_gift_count,
+                sub_plan,
+            },
+            UserNoticeEvent::GiftPaidUpgrade {
+                gifter_login,
+                gifter_name,
+                promotion,
+            } => Self::GiftPaidUpgrade {
+                gifter_login,
+                gifter_name,
+                promotion,
+            },
+            UserNoticeEvent::AnonGiftPaidUpgrade { promotion } => {
+                Self::AnonGiftPaidUpgrade { promotion }
+            }
+            UserNoticeEvent::Ritual { ritual_name } => Self::Ritual { ritual_name },
+            UserNoticeEvent::BitsBadgeTier { threshold } => Self::BitsBadgeTier { threshold },
+            _ => Self::from_params(event_id, source),
+        }
+    }
+
+    fn from_params(event_id: &str, source: &IRCMessage) -> Self {
+        let mut params = source
+            .tags
+            .0
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = key.strip_prefix("msg-param-")?;
+                Some((key.to_string(), value.clone().unwrap_or_default()))
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        match event_id {
+            "announcement" => Self::Announcement {
+                color: params.remove("color").filter(|color| !color.is_empty()),
+            },
+            _ => Self::Other { params },
+        }
+    }
+}
+
+impl From<UserNoticeMessage> for UserNotice {
+    fn from(message: UserNoticeMessage) -> Self {
+        let event = UserNoticeKind::from_event(message.event, &message.event_id, &message.source);
+
+        Self {
+            channel: message.channel_login,
+            room_id: message.channel_id,
+            username: message.sender.login,
+            user_id: message.sender.id,
+            display_name: message.sender.name,
+            message_id: message.message_id,
+            event_id: message.event_id,
+            event,
+            system_message: message.system_message,
+            message: message.message_text,
+            color: message.name_color.map(|color| color.to_string()),
+            badges: message.badges,
+            badge_info: message.badge_info,
+            emotes: message.emotes,
+            sent_at: message.server_timestamp,
+            raw: Some(raw_line(&message.source)),
+        }
+    }
+}
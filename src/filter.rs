@@ -0,0 +1,171 @@
+use crate::config::Config;
+use crate::entities::chat::ChatMessage;
+use crate::entities::event::Event;
+use crate::error::Error;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// One stage of the filter pipeline, declared in order as `[[filter]]` tables with a `type`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FilterConfig {
+    /// Keep only chat messages matching `pattern`.
+    Include { pattern: String },
+    /// Drop chat messages matching `pattern`.
+    Exclude { pattern: String },
+    /// Keep only chat messages from `users`.
+    AllowUsers { users: Vec<String> },
+    /// Drop chat messages from `users`.
+    DenyUsers { users: Vec<String> },
+    /// Remove zero-width and other invisible characters from chat messages.
+    StripInvisible,
+    /// Cut chat messages down to `max_chars` characters.
+    Truncate { max_chars: usize },
+    /// Turn `/me` and `\x01ACTION ...\x01` left in chat text into the `is_action` flag.
+    NormalizeAction,
+}
+
+/// A compiled `FilterConfig`.
+#[derive(Debug, Clone)]
+pub enum Stage {
+    Include(Regex),
+    Exclude(Regex),
+    AllowUsers(HashSet<String>),
+    DenyUsers(HashSet<String>),
+    StripInvisible,
+    Truncate(usize),
+    NormalizeAction,
+}
+
+impl TryFrom<&FilterConfig> for Stage {
+    type Error = Error;
+
+    fn try_from(config: &FilterConfig) -> Result<Self, Self::Error> {
+        let users = |users: &[String]| users.iter().map(|user| user.to_lowercase()).collect();
+
+        Ok(match config {
+            FilterConfig::Include { pattern } => Stage::Include(compile(pattern)?),
+            FilterConfig::Exclude { pattern } => Stage::Exclude(compile(pattern)?),
+            FilterConfig::AllowUsers { users: allowed } => Stage::AllowUsers(users(allowed)),
+            FilterConfig::DenyUsers { users: denied } => Stage::DenyUsers(users(denied)),
+            FilterConfig::StripInvisible => Stage::StripInvisible,
+            FilterConfig::Truncate { max_chars } => Stage::Truncate(*max_chars),
+            FilterConfig::NormalizeAction => Stage::NormalizeAction,
+        })
+    }
+}
+
+impl Stage {
+    /// Returns `false` if the message should be dropped.
+    fn apply(&self, message: &mut ChatMessage) -> bool {
+        match self {
+            Stage::Include(pattern) => pattern.is_match(&message.message),
+            Stage::Exclude(pattern) => !pattern.is_match(&message.message),
+            Stage::AllowUsers(users) => users.contains(&message.username.to_lowercase()),
+            Stage::DenyUsers(users) => !users.contains(&message.username.to_lowercase()),
+            Stage::StripInvisible => {
+                if message.message.chars().any(is_invisible) {
+                    message.message = message
+                        .message
+                        .chars()
+                        .filter(|c| !is_invisible(*c))
+                        .collect::<String>()
+                        .trim_end()
+                        .to_string();
+                }
+                true
+            }
+            Stage::Truncate(max_chars) => {
+                if let Some((end, _)) = message.message.char_indices().nth(*max_chars) {
+                    message.message.truncate(end);
+                }
+                true
+            }
+            Stage::NormalizeAction => {
+                if let Some(text) = action_text(&message.message) {
+                    message.message = text.to_string();
+                    message.is_action = true;
+                }
+                true
+            }
+        }
+    }
+}
+
+/// Drops, rewrites and tags events before they are buffered. Stages run in order and act on
+/// chat messages; other events pass through unchanged.
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    pub fn new(stages: Vec<Stage>) -> Self {
+        Self { stages }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Runs `event` through every stage. Returns `None` if a stage dropped it.
+    pub fn apply(&self, mut event: Event) -> Option<Event> {
+        if let Event::Chat(message) = &mut event {
+            for stage in &self.stages {
+                if !stage.apply(message) {
+                    return None;
+                }
+            }
+        }
+        Some(event)
+    }
+}
+
+impl TryFrom<&Config> for Pipeline {
+    type Error = Error;
+
+    fn try_from(config: &Config) -> Result<Self, Self::Error> {
+        let stages = config
+            .filters
+            .iter()
+            .flatten()
+            .map(Stage::try_from)
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(stages))
+    }
+}
+
+fn compile(pattern: &str) -> Result<Regex, Error> {
+    Regex::new(pattern).map_err(|e| Error::FailedToParse {
+        key: "filter pattern".to_string(),
+        value: pattern.to_string(),
+        error: Some(e.to_string()),
+    })
+}
+
+/// Characters that render as nothing, such as the tag character chat clients append to get
+/// around Twitch's duplicate message check. The zero-width joiner is kept, since emoji need it.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{180E}'
+            | '\u{200B}'
+            | '\u{200C}'
+            | '\u{200E}'
+            | '\u{200F}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{206A}'..='\u{206F}'
+            | '\u{FEFF}'
+            | '\u{E0000}'..='\u{E007F}'
+    )
+}
+
+fn action_text(message: &str) -> Option<&str> {
+    if let Some(text) = message.strip_prefix("\u{1}ACTION ") {
+        return Some(text.strip_suffix('\u{1}').unwrap_or(text));
+    }
+    message.strip_prefix("/me ")
+}
@@ -1,68 +1,551 @@
-use crate::config::Config;
-use crate::entities::chat::ChatMessage;
-use crate::logger::db_logger::DbLogger;
-use std::ops::Add;
+use crate::config::{BatchConfig, ChannelConfig, Config};
+use crate::entities::event::Event;
+use crate::entities::lifecycle::{Lifecycle, LifecycleKind};
+use crate::error::Error;
+use crate::filter::Pipeline;
+use crate::logger::sink::Sink;
+use crate::spool::Spool;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use log::{debug, trace};
+use log::{debug, error, info, warn};
 
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 
-use tokio::sync::mpsc::Receiver;
+use tokio::select;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::oneshot;
+use tokio::time::{sleep, sleep_until, Instant};
 
-use tokio::time::{interval, sleep, sleep_until};
-use tokio::{join, pin, select};
+type FlushReply = oneshot::Sender<Result<usize, Error>>;
+
+/// The version logged with `Started` and `Stopped` events.
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// How long to wait before retrying a failed append to the spool, doubling up to the maximum.
+const SPOOL_RETRY_MIN: Duration = Duration::from_millis(100);
+const SPOOL_RETRY_MAX: Duration = Duration::from_secs(10);
+
+/// The most events taken off the queue, and spooled, at once.
+const MAX_RECEIVED: usize = 256;
+
+enum Received {
+    Messages(Vec<Event>),
+    Flush(FlushReply),
+    Due,
+    Closed,
+}
+
+/// Counters for one channel since the logger started.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ChannelStats {
+    /// Events received from the client.
+    pub received: u64,
+    /// Events dropped by the filter pipeline or the channel's settings.
+    pub filtered: u64,
+    /// Events written, by sink.
+    pub written: BTreeMap<String, u64>,
+    /// When the last chat message was sent.
+    pub last_message_at: Option<DateTime<Utc>>,
+}
+
+pub type Stats = BTreeMap<String, ChannelStats>;
+
+/// When a sink writes the events buffered for it: as soon as `max_events` events or
+/// `max_bytes` bytes are buffered, or `max_age` after the oldest of them was. A sink that failed
+/// is retried after `max_age`, in batches of at most `max_events`.
+#[derive(Debug, Clone, Copy)]
+pub struct BatchPolicy {
+    pub max_events: usize,
+    pub max_age: Duration,
+    pub max_bytes: usize,
+}
+
+impl Default for BatchPolicy {
+    fn default() -> Self {
+        Self {
+            max_events: 1000,
+            max_age: Duration::from_secs(5),
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl From<&BatchConfig> for BatchPolicy {
+    fn from(config: &BatchConfig) -> Self {
+        let default = Self::default();
+        Self {
+            max_events: config.max_batch_size.unwrap_or(default.max_events),
+            max_age: config
+                .max_batch_age_ms
+                .map_or(default.max_age, Duration::from_millis),
+            max_bytes: config.max_batch_bytes.unwrap_or(default.max_bytes),
+        }
+    }
+}
 
 pub struct MessageHandler {
-    rx: Receiver<ChatMessage>,
-    config: Config,
-    db_logger: DbLogger,
+    rx: Receiver<Event>,
+    channels: HashMap<String, ChannelConfig>,
+    pipeline: Pipeline,
+    outputs: Vec<Output>,
+    spool: Option<Spool>,
+    pending: Vec<Event>,
+    /// The sequence number of the next event routed, counted from the first event in the spool.
+    sequence: u64,
+    /// The sequence number of the first event in the spool.
+    spooled_from: u64,
+    /// The channels to log `Started` and `Stopped` events for, if the handler logs them.
+    lifecycle: Option<BTreeSet<String>>,
+    flushes: Receiver<FlushReply>,
+    handle: HandlerHandle,
 }
 
 impl MessageHandler {
-    pub fn new(config: &Config, rx: Receiver<ChatMessage>, db_logger: DbLogger) -> Self {
+    pub fn new(config: &Config, rx: Receiver<Event>) -> Self {
+        let (flush_tx, flushes) = channel(16);
+
         Self {
             rx,
-            config: config.clone(),
-            db_logger,
+            channels: config
+                .channels
+                .iter()
+                .flatten()
+                .map(|(channel, settings)| {
+                    let login = channel.trim_start_matches('#').to_lowercase();
+                    (login, settings.clone())
+                })
+                .collect(),
+            pipeline: Pipeline::default(),
+            outputs: vec![],
+            spool: None,
+            pending: vec![],
+            sequence: 0,
+            spooled_from: 0,
+            lifecycle: None,
+            flushes,
+            handle: HandlerHandle {
+                flush: flush_tx,
+                stats: Arc::default(),
+            },
         }
     }
 
-    async fn recv_with_timeout(&mut self) -> Option<ChatMessage> {
-        let interval = Duration::seconds(1).to_std().unwrap();
-        let sleep = sleep(interval);
+    /// Writes every event to `sink` as well as the sinks added before, with the default
+    /// `BatchPolicy`. `name` identifies it in logs and stats.
+    pub fn with_sink(self, name: &str, sink: Box<dyn Sink>) -> Self {
+        self.with_batched_sink(name, sink, BatchPolicy::default())
+    }
+
+    /// Like `with_sink`, writing batches according to `policy`.
+    pub fn with_batched_sink(
+        mut self,
+        name: &str,
+        sink: Box<dyn Sink>,
+        policy: BatchPolicy,
+    ) -> Self {
+        self.outputs.push(Output {
+            name: name.to_string(),
+            sink,
+            policy,
+            buffer: vec![],
+            buffered: vec![],
+            bytes: 0,
+            deadline: None,
+            failed: false,
+        });
+        self
+    }
 
-        pin!(sleep, interval);
+    /// Runs every event through `pipeline` before buffering it.
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
+    /// Writes every event to `spool` before buffering it. `pending` are the events left in the
+    /// spool by a previous run, which every sink writes before it is started.
+    pub fn with_spool(mut self, spool: Spool, pending: Vec<Event>) -> Self {
+        if !pending.is_empty() {
+            info!("Recovering {} events from the spool", pending.len());
+        }
+        self.pending = pending;
+        self.spool = Some(spool);
+        self
+    }
+
+    /// Logs a `Started` event for every configured channel when `run` starts, and a `Stopped`
+    /// event for them and the channels joined since once it ends.
+    pub fn with_lifecycle_events(mut self) -> Self {
+        self.lifecycle = Some(self.channels.keys().cloned().collect());
+        self
+    }
+
+    pub fn handle(&self) -> HandlerHandle {
+        self.handle.clone()
+    }
+
+    async fn log_lifecycle(&mut self, kind: LifecycleKind) {
+        let events = match &self.lifecycle {
+            Some(channels) => channels
+                .iter()
+                .map(|channel| Event::Lifecycle(Lifecycle::new(channel.clone(), kind.clone())))
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+        info!("Logger {} {}", VERSION, kind.name());
+        self.receive(events).await;
+    }
+
+    /// Waits for events, a flush request or the earliest batch deadline. Without buffered
+    /// events there is no deadline, so an idle handler only wakes for new events.
+    async fn next(&mut self) -> Received {
+        let deadline = self
+            .outputs
+            .iter()
+            .filter_map(|output| output.deadline)
+            .min();
 
         select! {
             message = self.rx.recv() => {
-               message
+               match message {
+                   Some(message) => {
+                       let mut messages = vec![message];
+                       while messages.len() < MAX_RECEIVED {
+                           match self.rx.try_recv() {
+                               Ok(message) => messages.push(message),
+                               Err(_) => break,
+                           }
+                       }
+                       Received::Messages(messages)
+                   }
+                   None => Received::Closed,
+               }
+            },
+            Some(reply) = self.flushes.recv() => {
+                Received::Flush(reply)
             },
-            _ = &mut sleep => {
-                 None
+            _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                 Received::Due
             }
         }
     }
 
-    pub async fn run(&mut self) {
-        let mut buffer = vec![];
-        let interval = Duration::seconds(5);
-        let mut next_flush_at = Utc::now().add(interval);
+    /// Buffers incoming events and writes them to each sink in batches, according to its
+    /// `BatchPolicy`, until every sender is dropped. A batch that a sink fails to write is kept
+    /// and retried.
+    ///
+    /// The events recovered from the spool are written first, then every sink is started.
+    /// Once the senders are gone, the queue is drained and every sink flushed one last time.
+    /// Returns an error if a sink was left with events it could not write.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        let pending = std::mem::take(&mut self.pending);
+        if !pending.is_empty() {
+            self.route(pending);
+            self.flush().await.ok();
+        }
+        for output in &mut self.outputs {
+            if let Err(e) = output.sink.start().await {
+                error!("Failed to start sink {}: {}", output.name, e);
+            }
+        }
+        self.log_lifecycle(LifecycleKind::Started {
+            version: VERSION.to_string(),
+        })
+        .await;
 
         loop {
-            let message = self.recv_with_timeout().await;
+            match self.next().await {
+                Received::Messages(messages) => {
+                    self.receive(messages).await;
+                    self.flush_ready().await.ok();
+                }
+                Received::Flush(reply) => {
+                    reply.send(self.flush().await).ok();
+                }
+                Received::Due => {
+                    self.flush_ready().await.ok();
+                }
+                Received::Closed => {
+                    self.log_lifecycle(LifecycleKind::Stopped {
+                        version: VERSION.to_string(),
+                    })
+                    .await;
+                    self.flush().await.ok();
+                    return self.shutdown().await;
+                }
+            }
+        }
+    }
+
+    /// Runs `received` through the filter pipeline and the channel settings, then spools and
+    /// buffers what is left.
+    ///
+    /// Nothing is buffered before it is spooled: while the spool cannot be written, the append
+    /// is retried and no more events are taken off the queue, whose overflow policy applies.
+    async fn receive(&mut self, received: Vec<Event>) {
+        let mut messages = Vec::with_capacity(received.len());
+        for message in received {
+            debug!("{:?}", message);
+            self.handle.record_received(&message);
+            if let (Some(channels), Event::Lifecycle(lifecycle)) = (&mut self.lifecycle, &message) {
+                if lifecycle.event == LifecycleKind::Joined {
+                    channels.insert(lifecycle.channel.clone());
+                }
+            }
+
+            let channel = message.channel().to_string();
+            let settings = self.channels.get(&channel);
+            match self.pipeline.apply(message) {
+                Some(message) if settings.is_none_or(|settings| settings.accepts(&message)) => {
+                    messages.push(message)
+                }
+                _ => self.handle.record_filtered(&channel),
+            }
+        }
+        if messages.is_empty() {
+            return;
+        }
+
+        if let Some(spool) = &mut self.spool {
+            let mut backoff = SPOOL_RETRY_MIN;
+            while let Err(e) = spool.append(&messages).await {
+                error!("{}, retrying in {:?}", e, backoff);
+                sleep(backoff).await;
+                backoff = (backoff * 2).min(SPOOL_RETRY_MAX);
+            }
+        }
+        self.route(messages);
+    }
 
-            if let Some(message) = message {
-                debug!("{:?}", message);
-                buffer.push(message);
+    /// Buffers `events` for the sinks their channels are written to.
+    fn route(&mut self, events: Vec<Event>) {
+        for event in events {
+            let sequence = self.sequence;
+            self.sequence += 1;
+            let bytes = serde_json::to_vec(&event).map_or(0, |json| json.len());
+
+            for output in &mut self.outputs {
+                let routed = self
+                    .channels
+                    .get(event.channel())
+                    .is_none_or(|channel| channel.routes_to(&output.name));
+                if routed {
+                    output.push(sequence, bytes, event.clone());
+                }
             }
+        }
+    }
+
+    /// Writes every sink's buffer. Returns how many events were written in total, or the first
+    /// error if any sink failed.
+    async fn flush(&mut self) -> Result<usize, Error> {
+        self.flush_where(|_| true).await
+    }
+
+    /// Writes the buffers of the sinks whose batch is full or due.
+    async fn flush_ready(&mut self) -> Result<usize, Error> {
+        let now = Instant::now();
+        self.flush_where(|output| output.is_ready(now)).await
+    }
 
-            let now = Utc::now();
+    /// Writes the buffers of the sinks matching `ready`, then drops the events every sink has
+    /// written from the spool.
+    async fn flush_where(&mut self, ready: impl Fn(&Output) -> bool) -> Result<usize, Error> {
+        let mut written = 0;
+        let mut result = Ok(());
 
-            if now >= next_flush_at {
-                next_flush_at = now.add(interval);
-                self.db_logger.create_log_batch(&buffer).await;
-                buffer = vec![];
+        for output in &mut self.outputs {
+            if output.buffer.is_empty() || !ready(output) {
+                continue;
             }
+
+            match output.write(&self.handle).await {
+                Ok(count) => written += count,
+                Err(e) => {
+                    error!(
+                        "Sink {} failed to write batch of {} events: {}",
+                        output.name,
+                        output.buffer.len(),
+                        e
+                    );
+                    if result.is_ok() {
+                        result = Err(e);
+                    }
+                }
+            }
+        }
+
+        self.compact_spool().await?;
+        result.map(|_| written)
+    }
+
+    /// Drops the events every sink has written from the spool: all of them once the buffers are
+    /// empty, or the written prefix once it is at least half the spool.
+    async fn compact_spool(&mut self) -> Result<(), Error> {
+        let spool = match &mut self.spool {
+            Some(spool) => spool,
+            None => return Ok(()),
+        };
+
+        let oldest = self
+            .outputs
+            .iter()
+            .filter_map(Output::oldest)
+            .min()
+            .unwrap_or(self.sequence);
+        let written = oldest - self.spooled_from;
+
+        if oldest == self.sequence {
+            spool.truncate().await?;
+        } else if written > 0 && written >= self.sequence - oldest {
+            spool.drop_first(written as usize).await?;
+        } else {
+            return Ok(());
+        }
+        self.spooled_from = oldest;
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        let mut unwritten = 0;
+        for output in &mut self.outputs {
+            if !output.buffer.is_empty() {
+                unwritten += output.buffer.len();
+                let fate = match self.spool {
+                    Some(_) => "left in the spool",
+                    None => "dropped",
+                };
+                warn!(
+                    "{} events for sink {} were not written and are {}",
+                    output.buffer.len(),
+                    output.name,
+                    fate
+                );
+            }
+            if let Err(e) = output.sink.shutdown().await {
+                error!("Failed to shut down sink {}: {}", output.name, e);
+            }
+        }
+
+        match unwritten {
+            0 => Ok(()),
+            _ => Err(Error::Other(format!(
+                "{} events were not written",
+                unwritten
+            ))),
+        }
+    }
+}
+
+/// A sink and the events it has yet to write.
+struct Output {
+    name: String,
+    sink: Box<dyn Sink>,
+    policy: BatchPolicy,
+    buffer: Vec<Event>,
+    /// The sequence number and size of each buffered event.
+    buffered: Vec<(u64, usize)>,
+    bytes: usize,
+    /// When the buffer is written, whether or not it is full.
+    deadline: Option<Instant>,
+    /// Whether the last write failed, in which case only the deadline triggers a retry.
+    failed: bool,
+}
+
+impl Output {
+    fn push(&mut self, sequence: u64, bytes: usize, event: Event) {
+        if self.buffer.is_empty() {
+            self.deadline = Some(Instant::now() + self.policy.max_age);
+        }
+        self.buffer.push(event);
+        self.buffered.push((sequence, bytes));
+        self.bytes += bytes;
+    }
+
+    fn oldest(&self) -> Option<u64> {
+        self.buffered.first().map(|(sequence, _)| *sequence)
+    }
+
+    fn is_ready(&self, now: Instant) -> bool {
+        let full =
+            self.buffer.len() >= self.policy.max_events || self.bytes >= self.policy.max_bytes;
+        self.deadline.is_some_and(|deadline| deadline <= now) || (full && !self.failed)
+    }
+
+    /// Writes the buffer in batches of at most `max_events`, removing each batch once written.
+    /// Returns how many events were written.
+    async fn write(&mut self, handle: &HandlerHandle) -> Result<usize, Error> {
+        let mut written = 0;
+        while !self.buffer.is_empty() {
+            let end = self.buffer.len().min(self.policy.max_events);
+            let result = match self.sink.write(&self.buffer[..end]).await {
+                Ok(()) => self.sink.flush().await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                self.failed = true;
+                self.deadline = Some(Instant::now() + self.policy.max_age);
+                return Err(e);
+            }
+
+            handle.record_written(&self.name, &self.buffer[..end]);
+            self.buffer.drain(..end);
+            self.bytes -= self
+                .buffered
+                .drain(..end)
+                .map(|(_, bytes)| bytes)
+                .sum::<usize>();
+            written += end;
+        }
+
+        self.deadline = None;
+        self.failed = false;
+        Ok(written)
+    }
+}
+
+/// Flushes a running `MessageHandler` and reads its counters.
+#[derive(Debug, Clone)]
+pub struct HandlerHandle {
+    flush: Sender<FlushReply>,
+    stats: Arc<Mutex<Stats>>,
+}
+
+impl HandlerHandle {
+    /// Writes the buffered events now. Returns how many were written.
+    pub async fn flush(&self) -> Result<usize, Error> {
+        let stopped = || Error::Other("Message handler stopped".to_string());
+        let (reply, result) = oneshot::channel();
+        self.flush.send(reply).await.map_err(|_| stopped())?;
+        result.await.map_err(|_| stopped())?
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().clone()
+    }
+
+    fn record_received(&self, event: &Event) {
+        let mut stats = self.stats.lock().unwrap();
+        let channel = stats.entry(event.channel().to_string()).or_default();
+        channel.received += 1;
+        if let Event::Chat(message) = event {
+            channel.last_message_at = channel.last_message_at.max(Some(message.sent_at));
+        }
+    }
+
+    fn record_filtered(&self, channel: &str) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.entry(channel.to_string()).or_default().filtered += 1;
+    }
+
+    fn record_written(&self, sink: &str, events: &[Event]) {
+        let mut stats = self.stats.lock().unwrap();
+        for event in events {
+            let channel = stats.entry(event.channel().to_string()).or_default();
+            *channel.written.entry(sink.to_string()).or_default() += 1;
         }
     }
 }
@@ -1,9 +1,17 @@
 extern crate core;
 
+pub mod admin;
 pub mod client;
 pub mod config;
+pub mod coverage;
+pub mod credentials;
 pub mod entities;
 pub mod error;
+pub mod filter;
 pub mod handler;
 pub mod logger;
+pub mod queue;
+pub mod replay;
+pub mod spool;
+pub mod transport;
 pub mod utils;
@@ -1,15 +1,28 @@
 use crate::config::Config;
+use crate::entities::event::Event;
 use crate::error::Error;
+use crate::logger::sink::Sink;
 use crate::logger::value_logger::ValueLogger;
+use crate::utils::chat_message_format::ChatMessageFormat;
+use async_trait::async_trait;
 use log::{debug, error, info, trace, warn, Level};
 
 pub struct ConsoleLogger {
     pub level: Level,
+    pub format: ChatMessageFormat,
 }
 
 impl ConsoleLogger {
     pub fn new(level: Level) -> Self {
-        Self { level }
+        Self {
+            level,
+            format: ChatMessageFormat::Simple,
+        }
+    }
+
+    pub fn with_format(mut self, format: ChatMessageFormat) -> Self {
+        self.format = format;
+        self
     }
 }
 
@@ -25,6 +38,18 @@ impl ValueLogger<&str> for ConsoleLogger {
     }
 }
 
+/// Logs one line per event at `level`.
+#[async_trait]
+impl Sink for ConsoleLogger {
+    async fn write(&mut self, events: &[Event]) -> Result<(), Error> {
+        for event in events {
+            let line = self.format.format_event(event);
+            self.log(&line);
+        }
+        Ok(())
+    }
+}
+
 impl TryFrom<&Config> for ConsoleLogger {
     type Error = Error;
 
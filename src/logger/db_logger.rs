@@ -1,59 +1,134 @@
 use crate::config::Config;
-use crate::entities::chat::ChatMessage;
+use crate::coverage::CoverageRecords;
+use crate::entities::event::Event;
+use crate::entities::presence::PresenceSession;
+use crate::entities::room_state::ChannelState;
 use crate::error::Error;
+use crate::logger::migrations::{self, Migration};
+use crate::logger::statements::{
+    activity_query, channel_state, channel_state_query, coverage_records, gap_query,
+    lifecycle_query, lifecycle_seed_query, presence_table, session_table, sessions_query,
+    LifecycleRow, Statements, BULK_INSERT_THRESHOLD,
+};
+use crate::logger::storage::Storage;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 
+/// Logs events to Postgres.
 pub struct DbLogger {
     pool: PgPool,
-    table_name: String,
+    statements: Statements,
+    bulk_insert_threshold: usize,
 }
 
 impl DbLogger {
-    pub fn new(config: &Config, pool: PgPool) -> Self {
-        Self {
+    pub fn new(config: &Config, pool: PgPool) -> Result<Self, Error> {
+        let table = config.table_name()?;
+
+        Ok(Self {
             pool,
-            table_name: config.db_table.clone().unwrap(),
-        }
+            statements: Statements::new(table),
+            bulk_insert_threshold: BULK_INSERT_THRESHOLD,
+        })
+    }
+
+    /// Batches with at least `rows` chat messages insert them with one multi-row `INSERT` per
+    /// `statements::BULK_INSERT_MAX_ROWS` messages instead of one per message. Chat is inserted
+    /// before the other events in the batch.
+    pub fn with_bulk_insert_threshold(mut self, rows: usize) -> Self {
+        self.bulk_insert_threshold = rows;
+        self
+    }
+}
+
+#[async_trait]
+impl Storage for DbLogger {
+    async fn migrate(&self) -> Result<Vec<&'static Migration>, Error> {
+        migrations::migrate(&self.pool, self.statements.table()).await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<&'static Migration>, Error> {
+        migrations::pending(&self.pool, self.statements.table()).await
+    }
+
+    async fn create_log_batch(&mut self, events: &[Event]) -> Result<(), Error> {
+        self.statements
+            .write_batch(&self.pool, events, self.bulk_insert_threshold)
+            .await
     }
 
-    pub async fn create_log(&mut self, message: &ChatMessage) -> Result<(), Error> {
+    async fn close_stale_sessions(&mut self) -> Result<u64, Error> {
+        let table = self.statements.table();
         let query = format!(
-            "INSERT INTO {} (username, message, channel, sent_at) VALUES ($1, $2, $3, $4)",
-            self.table_name
+            "UPDATE {sessions} AS s SET close_reason = 'restart', left_at = GREATEST(s.joined_at, \
+             (SELECT MAX(recorded_at) FROM {presence} WHERE channel = s.channel), \
+             (SELECT MAX(sent_at) FROM {chat} WHERE channel = s.channel)) \
+             WHERE s.left_at IS NULL",
+            sessions = session_table(table),
+            presence = presence_table(table),
+            chat = table,
         );
-        sqlx::query(&query)
-            .bind(&message.username)
-            .bind(&message.message)
-            .bind(&message.channel)
-            .bind(message.sent_at)
-            .execute(&self.pool)
-            .await
-            .unwrap();
+        let result = sqlx::query(&query).execute(&self.pool).await?;
 
-        Ok(())
+        Ok(result.rows_affected())
     }
 
-    pub async fn create_log_batch(&mut self, messages: &[ChatMessage]) -> Result<(), Error> {
-        if messages.is_empty() {
-            return Ok(());
-        }
+    async fn channel_state_at(
+        &self,
+        channel: &str,
+        at: DateTime<Utc>,
+    ) -> Result<ChannelState, Error> {
+        let row = sqlx::query_as(&channel_state_query(self.statements.table()))
+            .bind(channel)
+            .bind(at)
+            .fetch_one(&self.pool)
+            .await?;
 
-        let query = format!(
-            "INSERT INTO {} (username, message, channel, sent_at) VALUES ($1, $2, $3, $4)",
-            self.table_name
+        Ok(channel_state(row))
+    }
+
+    async fn sessions_between(
+        &self,
+        channel: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<PresenceSession>, Error> {
+        let sessions = sqlx::query_as(&sessions_query(self.statements.table()))
+            .bind(channel)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(sessions)
+    }
+
+    async fn coverage_records(&self, since: DateTime<Utc>) -> Result<CoverageRecords, Error> {
+        let table = self.statements.table();
+        let activity = sqlx::query_as(&activity_query(table, "date_trunc('minute', sent_at)"))
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut lifecycle: Vec<LifecycleRow> = sqlx::query_as(&lifecycle_seed_query(table))
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+        lifecycle.extend(
+            sqlx::query_as(&lifecycle_query(table))
+                .bind(since)
+                .fetch_all(&self.pool)
+                .await?,
         );
-        let mut transaction = self.pool.begin().await.unwrap();
-        for message in messages {
-            sqlx::query(&query)
-                .bind(&message.username)
-                .bind(&message.message)
-                .bind(&message.channel)
-                .bind(message.sent_at)
-                .execute(&mut transaction)
-                .await
-                .unwrap();
-        }
-        transaction.commit().await.unwrap();
-        Ok(())
+        let gaps = sqlx::query_as(&gap_query(table))
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(coverage_records(activity, lifecycle, gaps))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
     }
 }
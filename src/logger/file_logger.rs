@@ -1,18 +1,42 @@
 use crate::config::Config;
+use crate::entities::event::Event;
 use crate::error::Error;
+use crate::logger::sink::Sink;
 use crate::logger::value_logger::ValueLogger;
+use crate::utils::chat_message_format::ChatMessageFormat;
+use async_trait::async_trait;
 use log::error;
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
 
 pub struct FileLogger {
     pub path: PathBuf,
+    pub format: ChatMessageFormat,
 }
 
 impl FileLogger {
     pub fn new(path: PathBuf) -> Self {
-        Self { path }
+        Self {
+            path,
+            format: ChatMessageFormat::default(),
+        }
+    }
+
+    pub fn with_format(mut self, format: ChatMessageFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn path_for(&self, channel: &str) -> PathBuf {
+        match self.path.to_str() {
+            Some(path) if path.contains("{channel}") => {
+                PathBuf::from(path.replace("{channel}", channel))
+            }
+            _ => self.path.clone(),
+        }
     }
 
     pub fn append_line(&self, line: &str) {
@@ -34,6 +58,37 @@ impl ValueLogger<&str> for FileLogger {
     }
 }
 
+/// Appends one line per event, creating the file if needed. A `{channel}` in the path is
+/// replaced by the event's channel, giving one file per channel.
+#[async_trait]
+impl Sink for FileLogger {
+    async fn write(&mut self, events: &[Event]) -> Result<(), Error> {
+        let mut files = BTreeMap::<PathBuf, String>::new();
+        for event in events {
+            let lines = files.entry(self.path_for(event.channel())).or_default();
+            lines.push_str(&self.format.format_event(event));
+            lines.push('\n');
+        }
+
+        for (path, lines) in files {
+            append(&path, &lines)
+                .await
+                .map_err(|e| Error::Other(format!("Failed to write {}: {}", path.display(), e)))?;
+        }
+        Ok(())
+    }
+}
+
+async fn append(path: &Path, lines: &str) -> std::io::Result<()> {
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .await?;
+    file.write_all(lines.as_bytes()).await?;
+    file.sync_data().await
+}
+
 impl TryFrom<&Config> for FileLogger {
     type Error = Error;
 
@@ -0,0 +1,349 @@
+use crate::error::Error;
+use crate::utils::table_name::TableName;
+use log::info;
+use sqlx::database::HasArguments;
+use sqlx::error::DatabaseError;
+use sqlx::{
+    ColumnIndex, Database, Decode, Encode, Executor, IntoArguments, PgPool, Postgres, Sqlite,
+    SqlitePool, Type,
+};
+
+/// A schema change, embedded from `migrations/`. See `render` for the placeholders in its SQL.
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    sql: &'static str,
+    /// A query for rows the migration would fail on or change, as `(what, count)` pairs. The
+    /// migration is refused while any count is not zero.
+    check: Option<&'static str>,
+}
+
+/// Every Postgres migration, in the order they are applied. Migrations are never edited once
+/// released; schema changes go in a new one, for both backends under the same version.
+pub static MIGRATIONS: [Migration; 9] = [
+    Migration {
+        version: 1,
+        description: "chat",
+        sql: include_str!("../../migrations/postgres/0001_chat.sql"),
+        check: None,
+    },
+    Migration {
+        version: 2,
+        description: "chat metadata",
+        sql: include_str!("../../migrations/postgres/0002_chat_metadata.sql"),
+        check: None,
+    },
+    Migration {
+        version: 3,
+        description: "event tables",
+        sql: include_str!("../../migrations/postgres/0003_event_tables.sql"),
+        check: None,
+    },
+    Migration {
+        version: 4,
+        description: "unique keys",
+        sql: include_str!("../../migrations/postgres/0004_unique_keys.sql"),
+        check: Some(include_str!(
+            "../../migrations/postgres/0004_unique_keys_check.sql"
+        )),
+    },
+    Migration {
+        version: 5,
+        description: "chat indexes",
+        sql: include_str!("../../migrations/postgres/0005_chat_indexes.sql"),
+        check: None,
+    },
+    Migration {
+        version: 6,
+        description: "chat action flag",
+        sql: include_str!("../../migrations/postgres/0006_chat_action.sql"),
+        check: None,
+    },
+    Migration {
+        version: 7,
+        description: "gaps",
+        sql: include_str!("../../migrations/postgres/0007_gaps.sql"),
+        check: None,
+    },
+    Migration {
+        version: 8,
+        description: "lifecycle",
+        sql: include_str!("../../migrations/postgres/0008_lifecycle.sql"),
+        check: None,
+    },
+    Migration {
+        version: 9,
+        description: "event keys",
+        sql: include_str!("../../migrations/postgres/0009_event_keys.sql"),
+        check: Some(include_str!(
+            "../../migrations/postgres/0009_event_keys_check.sql"
+        )),
+    },
+];
+
+/// The SQLite versions of `MIGRATIONS`, creating the same tables.
+pub static SQLITE_MIGRATIONS: [Migration; 9] = [
+    Migration {
+        version: 1,
+        description: "chat",
+        sql: include_str!("../../migrations/sqlite/0001_chat.sql"),
+        check: None,
+    },
+    Migration {
+        version: 2,
+        description: "chat metadata",
+        sql: include_str!("../../migrations/sqlite/0002_chat_metadata.sql"),
+        check: None,
+    },
+    Migration {
+        version: 3,
+        description: "event tables",
+        sql: include_str!("../../migrations/sqlite/0003_event_tables.sql"),
+        check: None,
+    },
+    Migration {
+        version: 4,
+        description: "unique keys",
+        sql: include_str!("../../migrations/sqlite/0004_unique_keys.sql"),
+        check: Some(include_str!(
+            "../../migrations/sqlite/0004_unique_keys_check.sql"
+        )),
+    },
+    Migration {
+        version: 5,
+        description: "chat indexes",
+        sql: include_str!("../../migrations/sqlite/0005_chat_indexes.sql"),
+        check: None,
+    },
+    Migration {
+        version: 6,
+        description: "chat action flag",
+        sql: include_str!("../../migrations/sqlite/0006_chat_action.sql"),
+        check: None,
+    },
+    Migration {
+        version: 7,
+        description: "gaps",
+        sql: include_str!("../../migrations/sqlite/0007_gaps.sql"),
+        check: None,
+    },
+    Migration {
+        version: 8,
+        description: "lifecycle",
+        sql: include_str!("../../migrations/sqlite/0008_lifecycle.sql"),
+        check: None,
+    },
+    Migration {
+        version: 9,
+        description: "event keys",
+        sql: include_str!("../../migrations/sqlite/0009_event_keys.sql"),
+        check: Some(include_str!(
+            "../../migrations/sqlite/0009_event_keys_check.sql"
+        )),
+    },
+];
+
+/// Applies the migrations `table` is missing, each in its own transaction, and returns them.
+/// Applied versions are recorded in `{table}_migrations`; concurrent runs wait for each other.
+pub async fn migrate(pool: &PgPool, table: &TableName) -> Result<Vec<&'static Migration>, Error> {
+    let history = table.with_suffix("migrations").to_string();
+    let mut connection = pool.acquire().await?;
+
+    sqlx::query("SELECT pg_advisory_lock(hashtext($1))")
+        .bind(&history)
+        .execute(&mut connection)
+        .await?;
+
+    let result = apply::<Postgres>(
+        &mut connection,
+        &MIGRATIONS,
+        table,
+        &history,
+        "version BIGINT PRIMARY KEY, description TEXT NOT NULL, \
+         applied_at TIMESTAMPTZ NOT NULL DEFAULT now()",
+    )
+    .await;
+
+    sqlx::query("SELECT pg_advisory_unlock(hashtext($1))")
+        .bind(&history)
+        .execute(&mut connection)
+        .await?;
+
+    result
+}
+
+/// Applies the SQLite migrations `table` is missing, like `migrate`. A SQLite database has a
+/// single writer, so there is nothing to lock.
+pub async fn migrate_sqlite(
+    pool: &SqlitePool,
+    table: &TableName,
+) -> Result<Vec<&'static Migration>, Error> {
+    let history = table.with_suffix("migrations").to_string();
+    let mut connection = pool.acquire().await?;
+
+    apply::<Sqlite>(
+        &mut connection,
+        &SQLITE_MIGRATIONS,
+        table,
+        &history,
+        "version INTEGER PRIMARY KEY, description TEXT NOT NULL, \
+         applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP",
+    )
+    .await
+}
+
+/// Returns the migrations `migrate` would apply to `table`, without applying them.
+pub async fn pending(pool: &PgPool, table: &TableName) -> Result<Vec<&'static Migration>, Error> {
+    let mut connection = pool.acquire().await?;
+    missing::<Postgres>(&mut connection, &MIGRATIONS, table).await
+}
+
+/// Returns the migrations `migrate_sqlite` would apply to `table`, without applying them.
+pub async fn pending_sqlite(
+    pool: &SqlitePool,
+    table: &TableName,
+) -> Result<Vec<&'static Migration>, Error> {
+    let mut connection = pool.acquire().await?;
+    missing::<Sqlite>(&mut connection, &SQLITE_MIGRATIONS, table).await
+}
+
+/// The `migrations` not recorded in `{table}_migrations`, all of them if it does not exist.
+async fn missing<DB: Database>(
+    connection: &mut DB::Connection,
+    migrations: &'static [Migration],
+    table: &TableName,
+) -> Result<Vec<&'static Migration>, Error>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    for<'q> i64: Decode<'q, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let query = format!("SELECT version FROM {}", table.with_suffix("migrations"));
+    let applied: Vec<i64> = match sqlx::query_scalar(&query).fetch_all(connection).await {
+        Ok(applied) => applied,
+        Err(sqlx::Error::Database(e)) if is_undefined_table(e.as_ref()) => vec![],
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(migrations
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .collect())
+}
+
+/// Whether `error` says the queried table does not exist: `undefined_table` on Postgres. SQLite
+/// only has a generic error code for it, so its message is matched instead.
+fn is_undefined_table(error: &dyn DatabaseError) -> bool {
+    error.code().as_deref() == Some("42P01") || error.message().starts_with("no such table")
+}
+
+async fn apply<DB: Database>(
+    connection: &mut DB::Connection,
+    migrations: &'static [Migration],
+    table: &TableName,
+    history: &str,
+    history_columns: &str,
+) -> Result<Vec<&'static Migration>, Error>
+where
+    for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+    for<'q> <DB as HasArguments<'q>>::Arguments: IntoArguments<'q, DB>,
+    for<'q> i64: Encode<'q, DB> + Decode<'q, DB> + Type<DB>,
+    for<'q> &'q str: Encode<'q, DB> + Type<DB>,
+    for<'r> String: Decode<'r, DB> + Type<DB>,
+    usize: ColumnIndex<DB::Row>,
+{
+    let query = format!(
+        "CREATE TABLE IF NOT EXISTS {} ({})",
+        history, history_columns
+    );
+    connection.execute(query.as_str()).await?;
+
+    let mut migrated = vec![];
+    for migration in missing::<DB>(&mut *connection, migrations, table).await? {
+        info!(
+            "Migrating {} to version {}: {}",
+            table, migration.version, migration.description
+        );
+        let mut transaction = sqlx::Connection::begin(&mut *connection).await?;
+        if let Some(check) = migration.check {
+            let found: Vec<(String, i64)> = sqlx::query_as(&render(check, table))
+                .fetch_all(&mut *transaction)
+                .await?;
+            let found = found
+                .into_iter()
+                .filter(|(_, count)| *count > 0)
+                .map(|(what, count)| format!("{} duplicate {}", count, what))
+                .collect::<Vec<_>>();
+            if !found.is_empty() {
+                return Err(Error::Other(format!(
+                    "Cannot migrate {} to version {} ({}): found {}. Remove them and run \
+                     `twitch-logger migrate` again",
+                    table,
+                    migration.version,
+                    migration.description,
+                    found.join(", ")
+                )));
+            }
+        }
+        (&mut *transaction)
+            .execute(render(migration.sql, table).as_str())
+            .await?;
+        let query = format!(
+            "INSERT INTO {} (version, description) VALUES ($1, $2)",
+            history
+        );
+        sqlx::query(&query)
+            .bind(migration.version)
+            .bind(migration.description)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+
+        migrated.push(migration);
+    }
+
+    Ok(migrated)
+}
+
+/// The length of the longest suffix, with its underscore, that a migration appends to the chat
+/// table's name.
+pub fn longest_suffix() -> usize {
+    MIGRATIONS
+        .iter()
+        .chain(&SQLITE_MIGRATIONS)
+        .flat_map(|migration| std::iter::once(migration.sql).chain(migration.check))
+        .flat_map(|sql| sql.split('{').skip(1))
+        .filter_map(|rest| rest.split_once('}')?.0.split_once(':'))
+        .map(|(_, suffix)| suffix.len() + 1)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Expands the placeholders in a migration: `{table}` is the chat table, `{table:suffix}` the
+/// table named `{table}_{suffix}` next to it, and `{name:suffix}` that name without its schema,
+/// for naming indexes.
+fn render(sql: &str, table: &TableName) -> String {
+    let mut rendered = String::with_capacity(sql.len());
+    let mut rest = sql;
+
+    while let Some(start) = rest.find('{') {
+        let end = start
+            + rest[start..]
+                .find('}')
+                .expect("unclosed placeholder in migration");
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..end];
+        let expanded = match placeholder.split_once(':') {
+            None if placeholder == "table" => table.to_string(),
+            Some(("table", suffix)) => table.with_suffix(suffix).to_string(),
+            Some(("name", suffix)) => table.with_suffix(suffix).unqualified(),
+            _ => panic!("unknown placeholder in migration: {}", placeholder),
+        };
+        rendered.push_str(&expanded);
+        rest = &rest[end + 1..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
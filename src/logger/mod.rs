@@ -1,4 +1,9 @@
 pub mod console_logger;
 pub mod db_logger;
 pub mod file_logger;
+pub mod migrations;
+pub mod sink;
+pub mod sqlite_logger;
+pub mod statements;
+pub mod storage;
 pub mod value_logger;
@@ -0,0 +1,69 @@
+use crate::entities::event::Event;
+use crate::error::Error;
+use crate::logger::storage::Storage;
+use async_trait::async_trait;
+
+/// An output the handler writes events to. Each sink gets every event in its own buffer, so a
+/// sink that fails keeps its events for the next flush without holding up the others.
+#[async_trait]
+pub trait Sink: Send {
+    /// Writes `events`. If this fails, the same events are passed again on the next flush.
+    async fn write(&mut self, events: &[Event]) -> Result<(), Error>;
+
+    /// Called once when the handler starts, after the events recovered from the spool are
+    /// written and before any new ones are.
+    async fn start(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Makes the events written so far durable. Called after every successful `write`.
+    async fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called once after the last flush, before the handler stops.
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Writes events to a database, each batch in one transaction.
+pub struct DbSink {
+    storage: Box<dyn Storage>,
+    close_stale_sessions: bool,
+}
+
+impl DbSink {
+    pub fn new(storage: Box<dyn Storage>) -> Self {
+        Self {
+            storage,
+            close_stale_sessions: false,
+        }
+    }
+
+    /// Closes the presence sessions a previous run left open when the handler starts. Only
+    /// then are the events it left in the spool written, JOINs among them.
+    pub fn closing_stale_sessions(mut self) -> Self {
+        self.close_stale_sessions = true;
+        self
+    }
+}
+
+#[async_trait]
+impl Sink for DbSink {
+    async fn write(&mut self, events: &[Event]) -> Result<(), Error> {
+        self.storage.create_log_batch(events).await
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        if self.close_stale_sessions {
+            self.storage.close_stale_sessions().await?;
+        }
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        self.storage.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use crate::config::Config;
+use crate::coverage::CoverageRecords;
+use crate::entities::event::Event;
+use crate::entities::presence::PresenceSession;
+use crate::entities::room_state::ChannelState;
+use crate::error::Error;
+use crate::logger::migrations::{self, Migration};
+use crate::logger::statements::{
+    activity_query, channel_state, channel_state_query, coverage_records, gap_query,
+    lifecycle_query, lifecycle_seed_query, presence_table, session_table, sessions_query,
+    LifecycleRow, Statements, BULK_INSERT_THRESHOLD,
+};
+use crate::logger::storage::Storage;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::SqlitePool;
+
+/// Logs events to a SQLite database, with the same tables as `DbLogger`.
+pub struct SqliteLogger {
+    pool: SqlitePool,
+    statements: Statements,
+    bulk_insert_threshold: usize,
+}
+
+impl SqliteLogger {
+    pub fn new(config: &Config, pool: SqlitePool) -> Result<Self, Error> {
+        let table = config.table_name()?;
+        if table.schema().is_some() {
+            return Err(Error::InvalidConfig(format!(
+                "db_table: {:?} has a schema, which SQLite does not support",
+                config.db_table.clone().unwrap_or_default()
+            )));
+        }
+
+        Ok(Self {
+            pool,
+            statements: Statements::new(table),
+            bulk_insert_threshold: BULK_INSERT_THRESHOLD,
+        })
+    }
+
+    /// Batches with at least `rows` chat messages insert them with multi-row `INSERT`s.
+    pub fn with_bulk_insert_threshold(mut self, rows: usize) -> Self {
+        self.bulk_insert_threshold = rows;
+        self
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteLogger {
+    async fn migrate(&self) -> Result<Vec<&'static Migration>, Error> {
+        migrations::migrate_sqlite(&self.pool, self.statements.table()).await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<&'static Migration>, Error> {
+        migrations::pending_sqlite(&self.pool, self.statements.table()).await
+    }
+
+    async fn create_log_batch(&mut self, events: &[Event]) -> Result<(), Error> {
+        self.statements
+            .write_batch(&self.pool, events, self.bulk_insert_threshold)
+            .await
+    }
+
+    async fn close_stale_sessions(&mut self) -> Result<u64, Error> {
+        // SQLite's `MAX` with several arguments is `NULL` if any of them is, hence the
+        // `COALESCE`s.
+        let table = self.statements.table();
+        let query = format!(
+            "UPDATE {sessions} AS s SET close_reason = 'restart', left_at = MAX(s.joined_at, \
+             COALESCE((SELECT MAX(recorded_at) FROM {presence} WHERE channel = s.channel), \
+             s.joined_at), \
+             COALESCE((SELECT MAX(sent_at) FROM {chat} WHERE channel = s.channel), s.joined_at)) \
+             WHERE s.left_at IS NULL",
+            sessions = session_table(table),
+            presence = presence_table(table),
+            chat = table,
+        );
+        let result = sqlx::query(&query).execute(&self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn channel_state_at(
+        &self,
+        channel: &str,
+        at: DateTime<Utc>,
+    ) -> Result<ChannelState, Error> {
+        let row = sqlx::query_as(&channel_state_query(self.statements.table()))
+            .bind(channel)
+            .bind(at)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(channel_state(row))
+    }
+
+    async fn sessions_between(
+        &self,
+        channel: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<PresenceSession>, Error> {
+        let sessions = sqlx::query_as(&sessions_query(self.statements.table()))
+            .bind(channel)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(sessions)
+    }
+
+    async fn coverage_records(&self, since: DateTime<Utc>) -> Result<CoverageRecords, Error> {
+        let table = self.statements.table();
+        let activity = sqlx::query_as(&activity_query(
+            table,
+            "strftime('%Y-%m-%d %H:%M:00', sent_at)",
+        ))
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut lifecycle: Vec<LifecycleRow> = sqlx::query_as(&lifecycle_seed_query(table))
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+        lifecycle.extend(
+            sqlx::query_as(&lifecycle_query(table))
+                .bind(since)
+                .fetch_all(&self.pool)
+                .await?,
+        );
+        let gaps = sqlx::query_as(&gap_query(table))
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(coverage_records(activity, lifecycle, gaps))
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
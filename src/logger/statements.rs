@@ -0,0 +1,619 @@
+use crate::coverage::CoverageRecords;
+use crate::entities::chat::ChatMessage;
+use crate::entities::event::Event;
+use crate::entities::gap::Gap;
+use crate::entities::lifecycle::{Lifecycle, LifecycleKind};
+use crate::entities::moderation::ModerationEvent;
+use crate::entities::presence::{Presence, PresenceAction};
+use crate::entities::room_state::{ChannelState, FollowersOnly, RoomState};
+use crate::entities::user_notice::UserNotice;
+use crate::error::Error;
+use crate::utils::table_name::TableName;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::database::HasArguments;
+use sqlx::postgres::Postgres;
+use sqlx::query::Query;
+use sqlx::sqlite::Sqlite;
+use sqlx::types::Json;
+use sqlx::{Database, Executor, IntoArguments, Pool};
+
+pub const CHAT_COLUMNS: &str = "username, message, channel, sent_at, message_id, user_id, \
+    display_name, color, badges, badge_info, emotes, bits, reply_parent_id, reply_parent, room_id, \
+    raw, is_action";
+
+/// Batches with at least this many chat messages use `Statements::bulk_chat_insert`.
+pub const BULK_INSERT_THRESHOLD: usize = 32;
+
+/// Postgres accepts at most 65535 parameters per statement and SQLite 32766, and chat has 17
+/// columns.
+const BULK_INSERT_MAX_ROWS: usize = 1000;
+
+const USER_NOTICE_COLUMNS: &str = "channel, room_id, username, user_id, display_name, \
+    message_id, event_id, event, system_message, message, color, badges, badge_info, emotes, \
+    sent_at, raw";
+
+const ROOM_STATE_COLUMNS: &str = "channel, room_id, emote_only, followers_only_minutes, \
+    slow_mode_seconds, subs_only, r9k, recorded_at, raw";
+
+pub type Arguments<'q, DB> = <DB as HasArguments<'q>>::Arguments;
+
+/// A parameter of an insert. Every backend binds each of these to its own column types, so the
+/// same rows can be written to Postgres and SQLite.
+pub enum Value<'q> {
+    Text(Option<&'q str>),
+    Integer(Option<i64>),
+    Boolean(Option<bool>),
+    Timestamp(DateTime<Utc>),
+    Json(Option<serde_json::Value>),
+}
+
+impl<'q> Value<'q> {
+    fn text(value: &'q str) -> Self {
+        Value::Text(Some(value))
+    }
+
+    fn json<T: Serialize>(value: &T) -> Self {
+        Value::Json(Some(to_json(value)))
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).expect("entities serialize to JSON")
+}
+
+/// A database the loggers can write rows of `Value`s to.
+pub trait Backend: Database {
+    fn bind<'q>(
+        query: Query<'q, Self, Arguments<'q, Self>>,
+        value: Value<'q>,
+    ) -> Query<'q, Self, Arguments<'q, Self>>;
+}
+
+impl Backend for Postgres {
+    fn bind<'q>(
+        query: Query<'q, Self, Arguments<'q, Self>>,
+        value: Value<'q>,
+    ) -> Query<'q, Self, Arguments<'q, Self>> {
+        match value {
+            Value::Text(value) => query.bind(value),
+            Value::Integer(value) => query.bind(value),
+            Value::Boolean(value) => query.bind(value),
+            Value::Timestamp(value) => query.bind(value),
+            Value::Json(value) => query.bind(value.map(Json)),
+        }
+    }
+}
+
+impl Backend for Sqlite {
+    fn bind<'q>(
+        query: Query<'q, Self, Arguments<'q, Self>>,
+        value: Value<'q>,
+    ) -> Query<'q, Self, Arguments<'q, Self>> {
+        match value {
+            Value::Text(value) => query.bind(value),
+            Value::Integer(value) => query.bind(value),
+            Value::Boolean(value) => query.bind(value),
+            Value::Timestamp(value) => query.bind(value),
+            Value::Json(value) => query.bind(value.map(Json)),
+        }
+    }
+}
+
+/// The inserts for every event type, written so that they run unchanged on each `Backend`.
+pub struct Statements {
+    table: TableName,
+    chat_insert: String,
+    user_notice_insert: String,
+    moderation_insert: String,
+    room_state_insert: String,
+    presence_insert: String,
+    session_open: String,
+    session_close: String,
+    channel_sessions_close: String,
+    gap_insert: String,
+    lifecycle_insert: String,
+}
+
+impl Statements {
+    pub fn new(table: TableName) -> Self {
+        Self {
+            chat_insert: insert_ignoring_duplicates(&table.to_string(), CHAT_COLUMNS),
+            user_notice_insert: insert_ignoring_duplicates(
+                &user_notice_table(&table),
+                USER_NOTICE_COLUMNS,
+            ),
+            moderation_insert: moderation_insert_query(&table),
+            room_state_insert: insert_ignoring_duplicates(
+                &room_state_table(&table),
+                ROOM_STATE_COLUMNS,
+            ),
+            presence_insert: insert_ignoring_duplicates(
+                &presence_table(&table),
+                "channel, username, action, recorded_at, raw",
+            ),
+            session_open: session_open_query(&table),
+            session_close: session_close_query(&table),
+            channel_sessions_close: channel_sessions_close_query(&table),
+            gap_insert: insert_ignoring_duplicates(
+                &gap_table(&table),
+                "channel, dropped, started_at, ended_at",
+            ),
+            lifecycle_insert: insert_ignoring_duplicates(
+                &lifecycle_table(&table),
+                "channel, event, version, reason, recorded_at",
+            ),
+            table,
+        }
+    }
+
+    pub fn table(&self) -> &TableName {
+        &self.table
+    }
+
+    /// Logs `events` in one transaction. Batches with at least `bulk_insert_threshold` chat
+    /// messages insert them with one multi-row `INSERT` per `BULK_INSERT_MAX_ROWS` messages
+    /// instead of one per message, before the other events in the batch.
+    pub async fn write_batch<DB: Backend>(
+        &self,
+        pool: &Pool<DB>,
+        events: &[Event],
+        bulk_insert_threshold: usize,
+    ) -> Result<(), Error>
+    where
+        for<'c> &'c mut DB::Connection: Executor<'c, Database = DB>,
+        for<'q> Arguments<'q, DB>: IntoArguments<'q, DB>,
+    {
+        if events.is_empty() {
+            return Ok(());
+        }
+
+        let chat = events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Chat(message) => Some(message),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let bulk = chat.len() >= bulk_insert_threshold;
+
+        let mut transaction = pool.begin().await?;
+        if bulk {
+            for chunk in chat.chunks(BULK_INSERT_MAX_ROWS) {
+                let sql = self.bulk_chat_insert(chunk.len());
+                chunk
+                    .iter()
+                    .flat_map(|message| chat_message_values(message))
+                    .fold(sqlx::query(&sql), DB::bind)
+                    .execute(&mut *transaction)
+                    .await?;
+            }
+        }
+        for event in events {
+            if bulk && matches!(event, Event::Chat(_)) {
+                continue;
+            }
+            for query in self.inserts::<DB>(event) {
+                query.execute(&mut *transaction).await?;
+            }
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    /// The queries that log `event`, in the order they must run.
+    pub fn inserts<'q, DB: Backend>(
+        &'q self,
+        event: &'q Event,
+    ) -> Vec<Query<'q, DB, Arguments<'q, DB>>> {
+        let rows = match event {
+            Event::Chat(message) => vec![(&self.chat_insert, chat_message_values(message))],
+            Event::UserNotice(notice) => {
+                vec![(&self.user_notice_insert, user_notice_values(notice))]
+            }
+            Event::Moderation(event) => {
+                vec![(&self.moderation_insert, moderation_event_values(event))]
+            }
+            Event::RoomState(state) => vec![(&self.room_state_insert, room_state_values(state))],
+            Event::Presence(presence) => self.presence_rows(presence),
+            Event::Gap(gap) => vec![(&self.gap_insert, gap_values(gap))],
+            Event::Lifecycle(lifecycle) => self.lifecycle_rows(lifecycle),
+        };
+
+        rows.into_iter()
+            .map(|(sql, values)| values.into_iter().fold(sqlx::query(sql), DB::bind))
+            .collect()
+    }
+
+    /// One multi-row `INSERT` for `rows` chat messages, skipping duplicates like the per-row
+    /// insert.
+    fn bulk_chat_insert(&self, rows: usize) -> String {
+        let columns = CHAT_COLUMNS.split(',').count();
+        let values = (0..rows)
+            .map(|row| {
+                let parameters = (1..=columns)
+                    .map(|column| format!("${}", row * columns + column))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("({})", parameters)
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(
+            "INSERT INTO {} ({}) VALUES {} ON CONFLICT DO NOTHING",
+            self.table, CHAT_COLUMNS, values
+        )
+    }
+
+    fn presence_rows<'q>(&'q self, presence: &'q Presence) -> Vec<(&'q String, Vec<Value<'q>>)> {
+        let session = match presence.action {
+            PresenceAction::Join => &self.session_open,
+            PresenceAction::Part => &self.session_close,
+        };
+
+        vec![
+            (
+                &self.presence_insert,
+                vec![
+                    Value::text(&presence.channel),
+                    Value::text(&presence.username),
+                    Value::text(presence.action.name()),
+                    Value::Timestamp(presence.recorded_at),
+                    Value::Text(presence.raw.as_deref()),
+                ],
+            ),
+            (
+                session,
+                vec![
+                    Value::text(&presence.channel),
+                    Value::text(&presence.username),
+                    Value::Timestamp(presence.recorded_at),
+                ],
+            ),
+        ]
+    }
+
+    /// Once the logger stops receiving a channel, it can no longer see anyone leave it, so every
+    /// session still open there is closed.
+    fn lifecycle_rows<'q>(&'q self, lifecycle: &'q Lifecycle) -> Vec<(&'q String, Vec<Value<'q>>)> {
+        let close_reason = match lifecycle.event {
+            LifecycleKind::Parted => "part",
+            LifecycleKind::Disconnected { .. } => "disconnect",
+            LifecycleKind::Stopped { .. } => "stop",
+            _ => return vec![(&self.lifecycle_insert, lifecycle_values(lifecycle))],
+        };
+
+        vec![
+            (&self.lifecycle_insert, lifecycle_values(lifecycle)),
+            (
+                &self.channel_sessions_close,
+                vec![
+                    Value::text(&lifecycle.channel),
+                    Value::Timestamp(lifecycle.recorded_at),
+                    Value::text(close_reason),
+                ],
+            ),
+        ]
+    }
+}
+
+pub fn user_notice_table(table: &TableName) -> String {
+    table.with_suffix("user_notices").to_string()
+}
+
+pub fn moderation_table(table: &TableName) -> String {
+    table.with_suffix("moderation").to_string()
+}
+
+pub fn room_state_table(table: &TableName) -> String {
+    table.with_suffix("room_state").to_string()
+}
+
+pub fn presence_table(table: &TableName) -> String {
+    table.with_suffix("presence").to_string()
+}
+
+pub fn session_table(table: &TableName) -> String {
+    table.with_suffix("presence_sessions").to_string()
+}
+
+pub fn gap_table(table: &TableName) -> String {
+    table.with_suffix("gaps").to_string()
+}
+
+pub fn lifecycle_table(table: &TableName) -> String {
+    table.with_suffix("lifecycle").to_string()
+}
+
+/// Counts chat messages sent since `$1` by channel and minute, with `minute` truncating
+/// `sent_at` in the backend's SQL.
+pub fn activity_query(table: &TableName, minute: &str) -> String {
+    format!(
+        "SELECT channel, {minute} AS minute, COUNT(*) FROM {table} WHERE sent_at >= $1 \
+         GROUP BY channel, minute ORDER BY channel, minute",
+        minute = minute,
+        table = table
+    )
+}
+
+/// Selects the lifecycle events recorded since `$1`, oldest first.
+pub fn lifecycle_query(table: &TableName) -> String {
+    format!(
+        "SELECT channel, event, version, reason, recorded_at FROM {} WHERE recorded_at >= $1 \
+         ORDER BY recorded_at",
+        lifecycle_table(table)
+    )
+}
+
+/// Selects the latest lifecycle events recorded in each channel before `$1`, as if recorded at
+/// `$1`: they give the state `lifecycle_query`'s events start from.
+pub fn lifecycle_seed_query(table: &TableName) -> String {
+    format!(
+        "SELECT l.channel, l.event, l.version, l.reason, $1 FROM {lifecycle} AS l \
+         JOIN (SELECT channel, MAX(recorded_at) AS latest FROM {lifecycle} \
+         WHERE recorded_at < $1 GROUP BY channel) AS seeds \
+         ON l.channel = seeds.channel AND l.recorded_at = seeds.latest",
+        lifecycle = lifecycle_table(table)
+    )
+}
+
+/// Selects the gaps that ended since `$1`.
+pub fn gap_query(table: &TableName) -> String {
+    format!(
+        "SELECT channel, dropped, started_at, ended_at FROM {} WHERE ended_at >= $1 \
+         ORDER BY started_at",
+        gap_table(table)
+    )
+}
+
+/// Selects, for `channel` `$1` at `$2`, the latest value of each setting `channel_state` takes.
+pub fn channel_state_query(table: &TableName) -> String {
+    let table = room_state_table(table);
+    let latest = |column: &str| {
+        format!(
+            "(SELECT {column} FROM {table} WHERE channel = $1 AND recorded_at <= $2 \
+             AND {column} IS NOT NULL ORDER BY recorded_at DESC LIMIT 1)",
+            column = column,
+            table = table
+        )
+    };
+    format!(
+        "SELECT {}, {}, {}, {}, {}",
+        latest("emote_only"),
+        latest("followers_only_minutes"),
+        latest("slow_mode_seconds"),
+        latest("subs_only"),
+        latest("r9k")
+    )
+}
+
+pub type ChannelStateRow = (
+    Option<bool>,
+    Option<i64>,
+    Option<i64>,
+    Option<bool>,
+    Option<bool>,
+);
+
+/// Turns the row selected by `channel_state_query` into a `ChannelState`.
+pub fn channel_state(row: ChannelStateRow) -> ChannelState {
+    let (emote_only, followers_only_minutes, slow_mode_seconds, subs_only, r9k) = row;
+    ChannelState {
+        emote_only,
+        followers_only: followers_only_minutes.map(FollowersOnly::from_minutes),
+        slow_mode_seconds: slow_mode_seconds.map(|seconds| seconds as u64),
+        subs_only,
+        r9k,
+    }
+}
+
+/// Selects the presence sessions in channel `$1` that overlap `$2..$3`.
+pub fn sessions_query(table: &TableName) -> String {
+    format!(
+        "SELECT channel, username, joined_at, left_at, close_reason FROM {} \
+         WHERE channel = $1 AND joined_at < $3 AND (left_at IS NULL OR left_at > $2) \
+         ORDER BY username, joined_at",
+        session_table(table)
+    )
+}
+
+pub type LifecycleRow = (
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    DateTime<Utc>,
+);
+
+/// Turns rows selected by the coverage queries into `CoverageRecords`, skipping lifecycle
+/// events of unknown kinds.
+pub fn coverage_records(
+    activity: Vec<(String, DateTime<Utc>, i64)>,
+    lifecycle: Vec<LifecycleRow>,
+    gaps: Vec<(String, i64, DateTime<Utc>, DateTime<Utc>)>,
+) -> CoverageRecords {
+    CoverageRecords {
+        activity: activity
+            .into_iter()
+            .map(|(channel, minute, count)| (channel, minute, count as u64))
+            .collect(),
+        lifecycle: lifecycle
+            .into_iter()
+            .filter_map(|(channel, event, version, reason, recorded_at)| {
+                let event = LifecycleKind::from_parts(&event, version, reason)?;
+                Some(Lifecycle {
+                    channel,
+                    event,
+                    recorded_at,
+                })
+            })
+            .collect(),
+        gaps: gaps
+            .into_iter()
+            .map(|(channel, dropped, started_at, ended_at)| Gap {
+                channel,
+                dropped: dropped as u64,
+                started_at,
+                ended_at,
+            })
+            .collect(),
+    }
+}
+
+/// A JOIN logged again, as when replaying, opens no second session.
+fn session_open_query(table: &TableName) -> String {
+    format!(
+        "INSERT INTO {sessions} (channel, username, joined_at) SELECT $1, $2, $3 \
+         WHERE NOT EXISTS (SELECT 1 FROM {sessions} \
+         WHERE channel = $1 AND username = $2 AND (left_at IS NULL OR joined_at = $3))",
+        sessions = session_table(table)
+    )
+}
+
+fn session_close_query(table: &TableName) -> String {
+    format!(
+        "UPDATE {} SET left_at = $3, close_reason = 'part' \
+         WHERE channel = $1 AND username = $2 AND left_at IS NULL",
+        session_table(table)
+    )
+}
+
+fn channel_sessions_close_query(table: &TableName) -> String {
+    format!(
+        "UPDATE {} SET left_at = $2, close_reason = $3 WHERE channel = $1 AND left_at IS NULL",
+        session_table(table)
+    )
+}
+
+/// Deletions only carry the target's login, so their user id is looked up from the logged message.
+fn moderation_insert_query(table: &TableName) -> String {
+    format!(
+        "INSERT INTO {} (channel, room_id, action, target_login, target_user_id, \
+         target_message_id, duration_seconds, message, sent_at, raw) \
+         VALUES ($1, $2, $3, $4, \
+         COALESCE($5, (SELECT user_id FROM {} WHERE message_id = $6 LIMIT 1)), \
+         $6, $7, $8, $9, $10) \
+         ON CONFLICT DO NOTHING",
+        moderation_table(table),
+        table
+    )
+}
+
+/// Inserts that skip rows already logged, e.g. by a retried batch or another logger instance.
+fn insert_ignoring_duplicates(table_name: &str, columns: &str) -> String {
+    format!(
+        "{} ON CONFLICT DO NOTHING",
+        insert_query(table_name, columns)
+    )
+}
+
+fn insert_query(table_name: &str, columns: &str) -> String {
+    let values = (1..=columns.split(',').count())
+        .map(|i| format!("${}", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        table_name, columns, values
+    )
+}
+
+fn chat_message_values(message: &ChatMessage) -> Vec<Value<'_>> {
+    vec![
+        Value::text(&message.username),
+        Value::text(&message.message),
+        Value::text(&message.channel),
+        Value::Timestamp(message.sent_at),
+        Value::Text(message.message_id.as_deref()),
+        Value::Text(message.user_id.as_deref()),
+        Value::Text(message.display_name.as_deref()),
+        Value::Text(message.color.as_deref()),
+        Value::json(&message.badges),
+        Value::json(&message.badge_info),
+        Value::json(&message.emotes),
+        Value::Integer(message.bits.map(|bits| bits as i64)),
+        Value::Text(
+            message
+                .reply_parent
+                .as_ref()
+                .map(|parent| parent.message_id.as_str()),
+        ),
+        Value::Json(message.reply_parent.as_ref().map(to_json)),
+        Value::Text(message.room_id.as_deref()),
+        Value::Text(message.raw.as_deref()),
+        Value::Boolean(Some(message.is_action)),
+    ]
+}
+
+fn user_notice_values(notice: &UserNotice) -> Vec<Value<'_>> {
+    vec![
+        Value::text(&notice.channel),
+        Value::text(&notice.room_id),
+        Value::text(&notice.username),
+        Value::text(&notice.user_id),
+        Value::text(&notice.display_name),
+        Value::text(&notice.message_id),
+        Value::text(&notice.event_id),
+        Value::json(&notice.event),
+        Value::text(&notice.system_message),
+        Value::Text(notice.message.as_deref()),
+        Value::Text(notice.color.as_deref()),
+        Value::json(&notice.badges),
+        Value::json(&notice.badge_info),
+        Value::json(&notice.emotes),
+        Value::Timestamp(notice.sent_at),
+        Value::Text(notice.raw.as_deref()),
+    ]
+}
+
+fn moderation_event_values(event: &ModerationEvent) -> Vec<Value<'_>> {
+    let action = &event.action;
+
+    vec![
+        Value::text(&event.channel),
+        Value::Text(event.room_id.as_deref()),
+        Value::text(action.name()),
+        Value::Text(action.target_login()),
+        Value::Text(action.target_user_id()),
+        Value::Text(action.target_message_id()),
+        Value::Integer(action.duration_seconds().map(|seconds| seconds as i64)),
+        Value::Text(action.message()),
+        Value::Timestamp(event.sent_at),
+        Value::Text(event.raw.as_deref()),
+    ]
+}
+
+fn room_state_values(state: &RoomState) -> Vec<Value<'_>> {
+    vec![
+        Value::text(&state.channel),
+        Value::text(&state.room_id),
+        Value::Boolean(state.emote_only),
+        Value::Integer(state.followers_only.map(FollowersOnly::to_minutes)),
+        Value::Integer(state.slow_mode_seconds.map(|seconds| seconds as i64)),
+        Value::Boolean(state.subs_only),
+        Value::Boolean(state.r9k),
+        Value::Timestamp(state.recorded_at),
+        Value::Text(state.raw.as_deref()),
+    ]
+}
+
+fn gap_values(gap: &Gap) -> Vec<Value<'_>> {
+    vec![
+        Value::text(&gap.channel),
+        Value::Integer(Some(gap.dropped as i64)),
+        Value::Timestamp(gap.started_at),
+        Value::Timestamp(gap.ended_at),
+    ]
+}
+
+fn lifecycle_values(lifecycle: &Lifecycle) -> Vec<Value<'_>> {
+    vec![
+        Value::text(&lifecycle.channel),
+        Value::text(lifecycle.event.name()),
+        Value::Text(lifecycle.event.version()),
+        Value::Text(lifecycle.event.reason()),
+        Value::Timestamp(lifecycle.recorded_at),
+    ]
+}
@@ -0,0 +1,87 @@
+use crate::config::Config;
+use crate::coverage::CoverageRecords;
+use crate::entities::event::Event;
+use crate::entities::presence::PresenceSession;
+use crate::entities::room_state::ChannelState;
+use crate::error::Error;
+use crate::logger::db_logger::DbLogger;
+use crate::logger::migrations::Migration;
+use crate::logger::sqlite_logger::SqliteLogger;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::PgPool;
+use std::str::FromStr;
+
+/// A database events are logged to. Every backend creates the same tables through its own
+/// migrations.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Creates or upgrades the schema. Returns the migrations that were applied.
+    async fn migrate(&self) -> Result<Vec<&'static Migration>, Error>;
+
+    /// Returns the migrations `migrate` would apply, without applying them.
+    async fn pending_migrations(&self) -> Result<Vec<&'static Migration>, Error>;
+
+    /// Logs `events` in one transaction.
+    async fn create_log_batch(&mut self, events: &[Event]) -> Result<(), Error>;
+
+    /// Closes presence sessions left open by a previous run at the last moment that run saw any
+    /// chat or presence activity in the channel, since the real leave time was never observed.
+    /// Returns how many were closed.
+    async fn close_stale_sessions(&mut self) -> Result<u64, Error>;
+
+    /// Returns the settings in effect in `channel` at `at`, taking each setting from the latest
+    /// `ROOMSTATE` at or before that instant which carried it. Settings never seen are `None`.
+    async fn channel_state_at(
+        &self,
+        channel: &str,
+        at: DateTime<Utc>,
+    ) -> Result<ChannelState, Error>;
+
+    /// Returns the presence sessions in `channel` that overlap `from..to`. Sessions that are still
+    /// open count as lasting until now.
+    async fn sessions_between(
+        &self,
+        channel: &str,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<PresenceSession>, Error>;
+
+    /// Reads what `coverage::coverage` needs about the period since `since`: chat messages counted
+    /// by channel and minute, lifecycle events and gaps. Each channel's lifecycle events start
+    /// with the latest one before `since`, moved to `since`, so a join before it still counts.
+    async fn coverage_records(&self, since: DateTime<Utc>) -> Result<CoverageRecords, Error>;
+
+    /// Closes the connection pool once every connection is returned to it.
+    async fn close(&self);
+}
+
+/// Connects to `db_url`, choosing the backend by its scheme: `postgres://` or `sqlite:`.
+pub async fn connect(config: &Config) -> Result<Box<dyn Storage>, Error> {
+    let url = config
+        .db_url
+        .clone()
+        .ok_or_else(|| Error::MissingConfig("db_url".to_string()))?;
+
+    match url.split_once(':').map(|(scheme, _)| scheme) {
+        Some("postgres" | "postgresql") => {
+            let pool = PgPool::connect(&url).await?;
+            Ok(Box::new(DbLogger::new(config, pool)?))
+        }
+        Some("sqlite") => {
+            let options = SqliteConnectOptions::from_str(&url)?.create_if_missing(true);
+            // One connection keeps an in-memory database alive and matches SQLite's single writer.
+            let pool = SqlitePoolOptions::new()
+                .max_connections(1)
+                .idle_timeout(None)
+                .max_lifetime(None)
+                .connect_with(options)
+                .await?;
+            Ok(Box::new(SqliteLogger::new(config, pool)?))
+        }
+        _ => Err(Error::InvalidConfig(
+            "db_url: expected a postgres:// or sqlite: URL".to_string(),
+        )),
+    }
+}
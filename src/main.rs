@@ -1,15 +1,25 @@
 #[macro_use]
 extern crate log;
 
-use sqlx::PgPool;
+use chrono::{NaiveDate, TimeZone, Utc};
+use std::path::PathBuf;
 use tokio::spawn;
 use tokio::task::JoinError;
+use twitch_logger::admin::AdminApi;
 use twitch_logger::client::Client;
-use twitch_logger::config::Config;
+use twitch_logger::config::{Config, SinkKind};
+use twitch_logger::coverage::CoverageOptions;
 
 use twitch_logger::error::Error;
-use twitch_logger::handler::MessageHandler;
-use twitch_logger::logger::db_logger::DbLogger;
+use twitch_logger::filter::Pipeline;
+use twitch_logger::handler::{BatchPolicy, MessageHandler};
+use twitch_logger::logger::console_logger::ConsoleLogger;
+use twitch_logger::logger::file_logger::FileLogger;
+use twitch_logger::logger::sink::{DbSink, Sink};
+use twitch_logger::logger::storage::{self, Storage};
+use twitch_logger::queue::EventQueue;
+use twitch_logger::replay::Replay;
+use twitch_logger::spool::Spool;
 
 fn setup_logger() {
     pretty_env_logger::formatted_timed_builder()
@@ -17,41 +27,262 @@ fn setup_logger() {
         .init();
 }
 
-async fn setup_db_pool(config: &Config) -> Result<PgPool, Error> {
-    let pool = PgPool::connect(&config.db_url.clone().unwrap()).await?;
-    Ok(pool)
+async fn setup_storage(config: &Config) -> Result<Box<dyn Storage>, Error> {
+    let storage = storage::connect(config).await?;
+    if config.auto_migrate.unwrap_or(true) {
+        storage.migrate().await?;
+    }
+    Ok(storage)
+}
+
+/// Adds the configured sinks to `handler`. When logging live chat, the presence sessions left
+/// open in the database are closed once the handler starts.
+async fn setup_sinks(
+    config: &Config,
+    mut handler: MessageHandler,
+    live: bool,
+) -> Result<MessageHandler, Error> {
+    for (name, sink) in config.sinks() {
+        let output: Box<dyn Sink> = match sink.kind {
+            SinkKind::Database => {
+                let sink = DbSink::new(setup_storage(config).await?);
+                match live {
+                    true => Box::new(sink.closing_stale_sessions()),
+                    false => Box::new(sink),
+                }
+            }
+            SinkKind::File { path, format } => Box::new(FileLogger::new(path).with_format(format)),
+            SinkKind::Console { level, format } => {
+                Box::new(ConsoleLogger::new(level).with_format(format))
+            }
+        };
+        handler = handler.with_batched_sink(&name, output, BatchPolicy::from(&sink.batch));
+    }
+    Ok(handler)
 }
 
 #[tokio::main]
 async fn main() {
     setup_logger();
-    let config = Config::new(None);
-    let pool = setup_db_pool(&config).await.unwrap();
-    let db_logger = DbLogger::new(&config, pool);
+    let config = match Config::new(None).and_then(|config| config.validate().map(|_| config)) {
+        Ok(config) => config,
+        Err(err) => {
+            error!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
 
-    let (tx, rx) = tokio::sync::mpsc::channel(1024);
-    let mut handler = MessageHandler::new(&config, rx, db_logger);
+    match args.first().map(String::as_str) {
+        None => run(config).await,
+        Some("replay") => replay(config, &args[1..]).await,
+        Some("migrate") => migrate(config).await,
+        Some("coverage") => coverage(config, &args[1..]).await,
+        Some(command) => {
+            error!("Unknown command: {}", command);
+            std::process::exit(2);
+        }
+    }
+}
 
-    let client_handle = spawn(async move {
-        let mut client = Client::try_from(&config).unwrap();
-        client.start(tx).await.unwrap();
-    });
+async fn run(config: Config) {
+    let queue = EventQueue::from_config(&config).unwrap();
+    let (tx, rx) = queue.start().unwrap();
+    let spool = Spool::from_config(&config).await.unwrap();
+    let pending = spool.pending().await.unwrap();
+    let handler = MessageHandler::new(&config, rx)
+        .with_pipeline(Pipeline::try_from(&config).unwrap())
+        .with_spool(spool, pending)
+        .with_lifecycle_events();
+    let mut handler = setup_sinks(&config, handler, true).await.unwrap();
+    let mut client = Client::try_from(&config).unwrap();
 
-    let handler_handle = spawn(async move {
-        handler.run().await;
-    });
+    if let Some(address) = config.admin_address {
+        let api = AdminApi::new(client.handle(), handler.handle(), tx.handle());
+        spawn(async move {
+            if let Err(err) = api.serve(address).await {
+                error!("{}", err);
+            }
+        });
+    }
 
-    let result = tokio::select!(
-        client_result = client_handle => client_result,
-        handler_result = handler_handle => handler_result,
+    let client_handle = client.handle();
+    let mut client_task = spawn(async move { client.start(tx).await });
+    let mut handler_task = spawn(async move { handler.run().await });
+
+    let mut client_result = None;
+    tokio::select!(
+        result = &mut client_task => client_result = Some(result),
+        result = &mut handler_task => exit_with(result),
+        signal = shutdown_signal() => info!("Received {}, shutting down", signal),
     );
 
+    // Parting every channel drops the sender once the client is done, so the handler drains
+    // the queue, flushes every sink and returns.
+    client_handle.stop();
+    let deadline = config.shutdown_timeout();
+    let stopped = tokio::time::timeout(deadline, async {
+        let client_result = match client_result {
+            Some(result) => result,
+            None => client_task.await,
+        };
+        match client_result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => error!("Client failed: {}", err),
+            Err(err) => error!("Client task failed: {}", err),
+        }
+        handler_task.await
+    })
+    .await;
+
+    match stopped {
+        Ok(result) => exit_with(result),
+        Err(_) => {
+            error!(
+                "Buffered events were not written within {} seconds",
+                deadline.as_secs()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Waits for Ctrl-C, or SIGTERM on Unix. Returns the signal's name.
+async fn shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+        tokio::select!(
+            _ = tokio::signal::ctrl_c() => "SIGINT",
+            _ = terminate.recv() => "SIGTERM",
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        tokio::signal::ctrl_c().await.unwrap();
+        "Ctrl-C"
+    }
+}
+
+/// Exits with status 0 if the handler wrote every event, or 1 if some were left unwritten.
+fn exit_with(result: Result<Result<(), Error>, JoinError>) -> ! {
     match result {
-        Ok(_) => info!("Exited."),
+        Ok(Ok(())) => {
+            info!("Exited.");
+            std::process::exit(0);
+        }
+        Ok(Err(err)) => {
+            error!("{}", err);
+            std::process::exit(1);
+        }
         Err(err) => handle_join_error(err),
     }
 }
 
-fn handle_join_error(err: JoinError) {
+/// Pushes raw IRC log files through the handler into the configured database.
+async fn replay(config: Config, paths: &[String]) {
+    if paths.is_empty() {
+        error!("Usage: twitch-logger replay <file>...");
+        std::process::exit(2);
+    }
+
+    let (tx, rx) = tokio::sync::mpsc::channel(1024);
+    let handler =
+        MessageHandler::new(&config, rx).with_pipeline(Pipeline::try_from(&config).unwrap());
+    let mut handler = setup_sinks(&config, handler, false).await.unwrap();
+    let handler_task = spawn(async move { handler.run().await });
+
+    for path in paths {
+        let replay = Replay::from_config(&config, PathBuf::from(path));
+        match replay.run(&tx).await {
+            Ok(stats) => info!(
+                "Replayed {}: {} events, {} ignored, {} invalid lines",
+                path, stats.events, stats.ignored, stats.invalid
+            ),
+            Err(err) => error!("Failed to replay {}: {}", path, err),
+        }
+    }
+
+    drop(tx);
+    exit_with(handler_task.await);
+}
+
+/// Applies any pending schema migrations and exits.
+async fn migrate(config: Config) {
+    let migrated = storage::connect(&config)
+        .await
+        .unwrap()
+        .migrate()
+        .await
+        .unwrap();
+    if migrated.is_empty() {
+        info!("Schema is up to date.");
+    }
+    for migration in migrated {
+        info!(
+            "Applied migration {}: {}",
+            migration.version, migration.description
+        );
+    }
+}
+
+/// Prints when each channel was logged, by day, and the holes likely in its logs.
+async fn coverage(config: Config, args: &[String]) {
+    let usage = || -> ! {
+        error!("Usage: twitch-logger coverage [--since <YYYY-MM-DD>] [<channel>...]");
+        std::process::exit(2);
+    };
+
+    let mut since = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+    let mut channels = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" => match args.next().map(|date| date.parse()) {
+                Some(Ok(date)) => since = date,
+                _ => usage(),
+            },
+            arg if arg.starts_with('-') => usage(),
+            channel => channels.push(channel.trim_start_matches('#').to_lowercase()),
+        }
+    }
+
+    // Only reads the database, so it is not migrated here.
+    let storage = storage::connect(&config).await.unwrap();
+    let pending = storage.pending_migrations().await.unwrap();
+    if let Some(migration) = pending.first() {
+        error!(
+            "The schema is out of date: {} migrations are pending, from version {} ({}). Run \
+             `twitch-logger migrate` first.",
+            pending.len(),
+            migration.version,
+            migration.description
+        );
+        std::process::exit(1);
+    }
+    let since = Utc.from_utc_datetime(&since.and_hms_opt(0, 0, 0).unwrap());
+    let records = storage.coverage_records(since).await.unwrap();
+    storage.close().await;
+
+    let report = twitch_logger::coverage::coverage(&records, &CoverageOptions::default());
+    let mut inferred = false;
+    for channel in report {
+        if channels.is_empty() || channels.contains(&channel.channel) {
+            inferred |= channel
+                .days
+                .iter()
+                .flat_map(|day| &day.intervals)
+                .any(|interval| interval.inferred);
+            print!("{}", channel);
+        }
+    }
+    if inferred {
+        println!("* worked out from messages alone, logged before lifecycle events were");
+    }
+}
+
+fn handle_join_error(err: JoinError) -> ! {
     panic!("Join error: {}", err);
 }
@@ -0,0 +1,429 @@
+use crate::config::Config;
+use crate::entities::event::Event;
+use crate::entities::gap::Gap;
+use crate::error::Error;
+use chrono::Utc;
+use dirs::data_local_dir;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{ErrorKind, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::fs::{create_dir_all, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::{Mutex, Notify};
+
+/// The most events handed to the handler ahead of the queue.
+const HANDOFF: usize = 256;
+
+/// What happens to an event the client receives while the queue to the handler is full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait for space, which holds up the IRC connection.
+    #[default]
+    Block,
+    /// Drop the oldest queued event to make space.
+    DropOldest,
+    /// Drop the new event.
+    DropNewest,
+    /// Append the event to the overflow file, and queue it again once the queue has drained.
+    Spill,
+}
+
+/// The state of the queue and the events that did not fit in it.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct QueueStats {
+    /// Events waiting in memory.
+    pub queued: usize,
+    /// Events waiting in the overflow file.
+    pub spilled: usize,
+    /// Events dropped, by channel.
+    pub dropped: BTreeMap<String, u64>,
+    /// Events that waited for space or were spilled, by channel.
+    pub delayed: BTreeMap<String, u64>,
+}
+
+/// The bounded queue between the client and the handler.
+///
+/// When it is full, new events are handled according to its `OverflowPolicy`. Dropped events
+/// are replaced by a `Gap` for their channel, queued once there is space again.
+pub struct EventQueue {
+    capacity: usize,
+    policy: OverflowPolicy,
+    overflow_file: Option<PathBuf>,
+}
+
+impl EventQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            policy: OverflowPolicy::default(),
+            overflow_file: None,
+        }
+    }
+
+    pub fn with_policy(mut self, policy: OverflowPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Where `OverflowPolicy::Spill` writes events. Events left in it by a previous run are
+    /// queued before any new ones.
+    pub fn with_overflow_file(mut self, path: PathBuf) -> Self {
+        self.overflow_file = Some(path);
+        self
+    }
+
+    /// Uses `queue_capacity` (1024 by default), `overflow` and `overflow_file`, by default
+    /// `twitch-logger/overflow.jsonl` in the local data directory.
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        let policy = config.overflow.unwrap_or_default();
+        let queue = Self::new(config.queue_capacity.unwrap_or(1024)).with_policy(policy);
+        if policy != OverflowPolicy::Spill {
+            return Ok(queue);
+        }
+
+        let path = match &config.overflow_file {
+            Some(path) => path.clone(),
+            None => data_local_dir()
+                .ok_or_else(|| Error::MissingConfig("overflow_file".to_string()))?
+                .join("twitch-logger")
+                .join("overflow.jsonl"),
+        };
+        Ok(queue.with_overflow_file(path))
+    }
+
+    /// Starts passing queued events on to the handler. Returns the client's end of the queue
+    /// and the handler's.
+    pub fn start(self) -> Result<(EventSender, Receiver<Event>), Error> {
+        if self.policy == OverflowPolicy::Spill && self.overflow_file.is_none() {
+            return Err(Error::MissingConfig("overflow_file".to_string()));
+        }
+
+        let (tx, rx) = channel(HANDOFF);
+        let shared = Arc::new(Shared {
+            capacity: self.capacity,
+            policy: self.policy,
+            state: Mutex::new(State {
+                recovered: self.overflow_file.is_none(),
+                ..State::default()
+            }),
+            overflow_file: self.overflow_file,
+            ready: Notify::new(),
+            space: Notify::new(),
+            closed: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        });
+        tokio::spawn(forward(shared.clone(), tx));
+
+        Ok((EventSender { shared }, rx))
+    }
+}
+
+/// The client's end of an `EventQueue`. Dropping it lets the handler stop once the queue is
+/// empty.
+pub struct EventSender {
+    shared: Arc<Shared>,
+}
+
+impl EventSender {
+    /// Queues `event`, applying the overflow policy if the queue is full. Fails once the handler
+    /// has stopped.
+    pub async fn send(&self, event: Event) -> Result<(), Error> {
+        let shared = &self.shared;
+        let mut state = shared.state.lock().await;
+        let mut waited = false;
+
+        loop {
+            if shared.stopped.load(Ordering::SeqCst) {
+                return Err(Error::Other("Message handler stopped".to_string()));
+            }
+
+            let full =
+                state.spilled > 0 || !state.recovered || state.events.len() >= shared.capacity;
+            if !full {
+                state.push(event);
+                break;
+            }
+
+            match shared.policy {
+                OverflowPolicy::Block => {
+                    if !waited {
+                        state.record_delayed(&event);
+                        waited = true;
+                    }
+                    drop(state);
+                    shared.space.notified().await;
+                    state = shared.state.lock().await;
+                }
+                OverflowPolicy::DropOldest => {
+                    state.drop_oldest();
+                    state.events.push_back(event);
+                    break;
+                }
+                OverflowPolicy::DropNewest => {
+                    state.record_dropped(&event);
+                    return Ok(());
+                }
+                OverflowPolicy::Spill => {
+                    let path = shared.overflow_file.as_deref().unwrap();
+                    match state.spill(&event, path).await {
+                        Ok(()) => state.record_delayed(&event),
+                        Err(e) => {
+                            error!("{}", e);
+                            state.record_dropped(&event);
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+
+        drop(state);
+        shared.ready.notify_one();
+        Ok(())
+    }
+
+    pub fn handle(&self) -> QueueHandle {
+        QueueHandle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::SeqCst);
+        self.shared.ready.notify_one();
+    }
+}
+
+/// Reads the counters of an `EventQueue`.
+#[derive(Clone)]
+pub struct QueueHandle {
+    shared: Arc<Shared>,
+}
+
+impl QueueHandle {
+    pub async fn stats(&self) -> QueueStats {
+        let state = self.shared.state.lock().await;
+        QueueStats {
+            queued: state.events.len(),
+            spilled: state.spilled,
+            dropped: state.dropped.clone(),
+            delayed: state.delayed.clone(),
+        }
+    }
+}
+
+struct Shared {
+    capacity: usize,
+    policy: OverflowPolicy,
+    overflow_file: Option<PathBuf>,
+    state: Mutex<State>,
+    /// Notified when events are queued or the sender is dropped.
+    ready: Notify,
+    /// Notified when an event leaves the queue or the handler stops.
+    space: Notify,
+    closed: AtomicBool,
+    stopped: AtomicBool,
+}
+
+#[derive(Default)]
+struct State {
+    events: VecDeque<Event>,
+    /// Events in the overflow file. They were received after every event in `events`.
+    spilled: usize,
+    /// Whether events left in the overflow file by a previous run have been queued.
+    recovered: bool,
+    /// How many bytes of the overflow file have been read back into `events`.
+    unspilled: u64,
+    file: Option<File>,
+    /// The gaps left by events dropped since the queue was last not full, by channel.
+    gaps: BTreeMap<String, Gap>,
+    dropped: BTreeMap<String, u64>,
+    delayed: BTreeMap<String, u64>,
+}
+
+impl State {
+    /// Queues `event` after the gaps left by the events dropped before it.
+    fn push(&mut self, event: Event) {
+        self.push_gaps();
+        self.events.push_back(event);
+    }
+
+    fn push_gaps(&mut self) {
+        let gaps = std::mem::take(&mut self.gaps);
+        self.events.extend(gaps.into_values().map(Event::Gap));
+    }
+
+    /// Drops the oldest queued event. A gap is taken back into the pending ones, so that the
+    /// events it stands for are still accounted for.
+    fn drop_oldest(&mut self) {
+        match self.events.pop_front() {
+            Some(Event::Gap(gap)) => match self.gaps.get_mut(&gap.channel) {
+                Some(pending) => pending.merge(gap),
+                None => {
+                    self.gaps.insert(gap.channel.clone(), gap);
+                }
+            },
+            Some(event) => self.record_dropped(&event),
+            None => {}
+        }
+    }
+
+    fn record_dropped(&mut self, event: &Event) {
+        let channel = event.channel();
+        let now = Utc::now();
+        *self.dropped.entry(channel.to_string()).or_default() += 1;
+        self.gaps
+            .entry(channel.to_string())
+            .or_insert_with(|| {
+                warn!("Event queue is full, dropping events from #{}", channel);
+                Gap::new(channel.to_string(), now)
+            })
+            .record(now);
+    }
+
+    fn record_delayed(&mut self, event: &Event) {
+        *self.delayed.entry(event.channel().to_string()).or_default() += 1;
+    }
+
+    async fn spill(&mut self, event: &Event, path: &Path) -> Result<(), Error> {
+        if self.file.is_none() {
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)
+                    .await
+                    .map_err(|e| overflow_error("create", dir, e))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await
+                .map_err(|e| overflow_error("open", path, e))?;
+            self.file = Some(file);
+        }
+
+        let mut line = serde_json::to_string(event)
+            .map_err(|e| Error::Other(format!("Failed to serialize event: {}", e)))?;
+        line.push('\n');
+        self.file
+            .as_mut()
+            .unwrap()
+            .write_all(line.as_bytes())
+            .await
+            .map_err(|e| overflow_error("write", path, e))?;
+        self.spilled += 1;
+        Ok(())
+    }
+
+    /// Queues up to `limit` events from the overflow file, reading on from where the last call
+    /// stopped, and empties the file once all of it is read. Events that cannot be read back are
+    /// dropped.
+    async fn unspill(&mut self, path: &Path, limit: usize) {
+        let finished = match File::open(path).await {
+            Ok(file) => match self.read_spilled(file, limit).await {
+                Ok(finished) => finished,
+                Err(e) => {
+                    error!("{}", overflow_error("read", path, e));
+                    true
+                }
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => true,
+            Err(e) => {
+                error!("{}", overflow_error("open", path, e));
+                true
+            }
+        };
+        if !finished {
+            return;
+        }
+
+        if self.unspilled > 0 {
+            let truncated = match &self.file {
+                Some(file) => file.set_len(0).await,
+                None => File::create(path).await.map(|_| ()),
+            };
+            if let Err(e) = truncated {
+                error!("{}", overflow_error("truncate", path, e));
+            }
+        }
+        self.unspilled = 0;
+        self.spilled = 0;
+        self.recovered = true;
+    }
+
+    /// Queues the events in `file` from `unspilled` on, one line at a time, until `limit` are
+    /// queued. Returns whether the end of the file was reached.
+    async fn read_spilled(&mut self, mut file: File, limit: usize) -> std::io::Result<bool> {
+        file.seek(SeekFrom::Start(self.unspilled)).await?;
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        let mut queued = 0;
+
+        while queued < limit {
+            line.clear();
+            let read = reader.read_line(&mut line).await?;
+            if read == 0 {
+                return Ok(true);
+            }
+            self.unspilled += read as u64;
+            match serde_json::from_str(line.trim_end()) {
+                Ok(event) => {
+                    self.events.push_back(event);
+                    self.spilled = self.spilled.saturating_sub(1);
+                    queued += 1;
+                }
+                Err(e) => warn!("Skipping spilled event {:?}: {}", line.trim_end(), e),
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Moves queued events to the handler, spilled ones once the queue has drained, until the
+/// sender is dropped and the queue is empty or the handler stops.
+async fn forward(shared: Arc<Shared>, tx: Sender<Event>) {
+    loop {
+        let closed = shared.closed.load(Ordering::SeqCst);
+        let next = {
+            let mut state = shared.state.lock().await;
+            if state.events.is_empty() && (state.spilled > 0 || !state.recovered) {
+                if let Some(path) = &shared.overflow_file {
+                    state.unspill(path, shared.capacity).await;
+                }
+            }
+            if state.events.is_empty() && closed {
+                state.push_gaps();
+            }
+            state.events.pop_front()
+        };
+
+        match next {
+            Some(event) => {
+                shared.space.notify_one();
+                if tx.send(event).await.is_err() {
+                    shared.stopped.store(true, Ordering::SeqCst);
+                    shared.space.notify_one();
+                    return;
+                }
+            }
+            None if closed => return,
+            None => shared.ready.notified().await,
+        }
+    }
+}
+
+fn overflow_error(action: &str, path: &Path, error: std::io::Error) -> Error {
+    Error::Other(format!(
+        "Failed to {} overflow file {}: {}",
+        action,
+        path.display(),
+        error
+    ))
+}
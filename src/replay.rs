@@ -0,0 +1,134 @@
+use crate::config::Config;
+use crate::entities::event::Event;
+use crate::error::Error;
+use crate::transport::parse_line;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use log::{debug, warn};
+use std::path::PathBuf;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc::Sender;
+use twitch_irc::message::ServerMessage;
+
+/// Feeds a file of raw IRC lines through the same path as messages received by `Client`.
+///
+/// Each line may be prefixed by the time it was received, either as an RFC 3339 timestamp or
+/// in square brackets (`[2023-04-01 18:00:00]`, read as UTC). That time is used for events
+/// Twitch sends without a `tmi-sent-ts` tag, such as `JOIN` and `ROOMSTATE`.
+pub struct Replay {
+    path: PathBuf,
+    store_raw: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReplayStats {
+    pub lines: u64,
+    pub events: u64,
+    pub ignored: u64,
+    pub invalid: u64,
+}
+
+impl Replay {
+    pub fn new(path: PathBuf, store_raw: bool) -> Self {
+        Self { path, store_raw }
+    }
+
+    pub fn from_config(config: &Config, path: PathBuf) -> Self {
+        Self::new(path, config.store_raw.unwrap_or_default())
+    }
+
+    pub async fn run(&self, sender: &Sender<Event>) -> Result<ReplayStats, Error> {
+        let file = File::open(&self.path)
+            .await
+            .map_err(|e| Error::Other(format!("Failed to open {}: {}", self.path.display(), e)))?;
+        let mut lines = BufReader::new(file).lines();
+        let mut stats = ReplayStats::default();
+
+        while let Some(line) = lines
+            .next_line()
+            .await
+            .map_err(|e| Error::Other(format!("Failed to read {}: {}", self.path.display(), e)))?
+        {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            stats.lines += 1;
+
+            match parse_replay_line(line) {
+                Ok(Some(mut event)) => {
+                    if !self.store_raw {
+                        event.clear_raw();
+                    }
+                    sender
+                        .send(event)
+                        .await
+                        .map_err(|_| Error::Other("Message handler stopped".to_string()))?;
+                    stats.events += 1;
+                }
+                Ok(None) => stats.ignored += 1,
+                Err(e) => {
+                    warn!("{}:{}: {}", self.path.display(), stats.lines, e);
+                    stats.invalid += 1;
+                }
+            }
+        }
+
+        debug!("Replayed {}: {:?}", self.path.display(), stats);
+        Ok(stats)
+    }
+}
+
+/// Parses one line of a raw IRC log. Returns `None` for messages that are not logged, such as
+/// `PING` or `CAP`.
+pub fn parse_replay_line(line: &str) -> Result<Option<Event>, Error> {
+    let (received_at, line) = split_timestamp(line);
+
+    let message = parse_line(line.to_string()).map_err(|e| Error::FailedToParse {
+        key: "IRC message".to_string(),
+        value: line.to_string(),
+        error: Some(e.to_string()),
+    })?;
+    let message = ServerMessage::try_from(message).map_err(|e| Error::FailedToParse {
+        key: "Twitch message".to_string(),
+        value: line.to_string(),
+        error: Some(e.to_string()),
+    })?;
+
+    let mut event = match Event::from_server_message(message) {
+        Some(event) => event,
+        None => return Ok(None),
+    };
+    if let Some(received_at) = received_at {
+        event.set_received_at(received_at);
+    }
+
+    Ok(Some(event))
+}
+
+fn split_timestamp(line: &str) -> (Option<DateTime<Utc>>, &str) {
+    if let Some(rest) = line.strip_prefix('[') {
+        if let Some((timestamp, rest)) = rest.split_once(']') {
+            if let Some(timestamp) = parse_timestamp(timestamp) {
+                return (Some(timestamp), rest.trim_start());
+            }
+        }
+    } else if let Some((timestamp, rest)) = line.split_once(' ') {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp) {
+            return (Some(timestamp.with_timezone(&Utc)), rest.trim_start());
+        }
+    }
+
+    (None, line)
+}
+
+fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(timestamp) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+
+    ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(timestamp, format).ok())
+        .map(|timestamp| Utc.from_utc_datetime(&timestamp))
+}
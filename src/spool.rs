@@ -0,0 +1,242 @@
+use crate::config::Config;
+use crate::entities::event::Event;
+use crate::error::Error;
+use dirs::data_local_dir;
+use log::warn;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use tokio::fs::{create_dir_all, rename, File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+
+/// An append-only file of events that have been received but not yet committed to the database.
+///
+/// Events are written to the spool, one JSON object per line, before they are taken off the
+/// client's queue, and dropped from it once every sink has written them. Whatever is left in it
+/// after a crash is written to the sinks on the next start.
+pub struct Spool {
+    path: PathBuf,
+    file: File,
+    /// How many bytes of whole events the spool holds.
+    len: u64,
+}
+
+impl Spool {
+    /// Opens the spool at `path`, creating it and its directory if needed.
+    pub async fn open(path: PathBuf) -> Result<Self, Error> {
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)
+                .await
+                .map_err(|e| spool_error("create", dir, e))?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|e| spool_error("open", &path, e))?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(|e| spool_error("read", &path, e))?
+            .len();
+
+        // A crash in the middle of an append leaves part of a line, which the next append would
+        // be written onto.
+        let len = whole_lines_len(&path, size)
+            .await
+            .map_err(|e| spool_error("read", &path, e))?;
+        if len < size {
+            warn!(
+                "Dropping the last {} bytes of spool {}, an event cut short",
+                size - len,
+                path.display()
+            );
+            file.set_len(len)
+                .await
+                .map_err(|e| spool_error("truncate", &path, e))?;
+        }
+
+        Ok(Self { path, file, len })
+    }
+
+    /// Opens `spool_file`, or `twitch-logger/spool.jsonl` in the local data directory.
+    pub async fn from_config(config: &Config) -> Result<Self, Error> {
+        let path = match &config.spool_file {
+            Some(path) => path.clone(),
+            None => data_local_dir()
+                .ok_or_else(|| Error::MissingConfig("spool_file".to_string()))?
+                .join("twitch-logger")
+                .join("spool.jsonl"),
+        };
+        Self::open(path).await
+    }
+
+    /// Reads the events left in the spool by a previous run. Lines that are not events are
+    /// skipped, and not counted by `drop_first` either.
+    pub async fn pending(&self) -> Result<Vec<Event>, Error> {
+        let file = File::open(&self.path)
+            .await
+            .map_err(|e| spool_error("open", &self.path, e))?;
+        let mut lines = BufReader::new(file).lines();
+        let mut events = vec![];
+
+        while let Some(line) = lines
+            .next_line()
+            .await
+            .map_err(|e| spool_error("read", &self.path, e))?
+        {
+            match serde_json::from_str(&line) {
+                Ok(event) => events.push(event),
+                Err(e) => warn!("Skipping spooled event {:?}: {}", line, e),
+            }
+        }
+
+        Ok(events)
+    }
+
+    /// Appends `events` and syncs them to disk. If that fails, the spool is reopened and
+    /// whatever part of them was written is cut off again, so the append can be retried.
+    pub async fn append(&mut self, events: &[Event]) -> Result<(), Error> {
+        let mut lines = String::new();
+        for event in events {
+            let line = serde_json::to_string(event)
+                .map_err(|e| Error::Other(format!("Failed to serialize event: {}", e)))?;
+            lines.push_str(&line);
+            lines.push('\n');
+        }
+
+        if let Err(e) = self.write(lines.as_bytes()).await {
+            self.roll_back().await;
+            return Err(e);
+        }
+        self.len += lines.len() as u64;
+        Ok(())
+    }
+
+    async fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.file
+            .write_all(bytes)
+            .await
+            .map_err(|e| spool_error("write", &self.path, e))?;
+        self.file
+            .sync_data()
+            .await
+            .map_err(|e| spool_error("sync", &self.path, e))
+    }
+
+    /// Reopens the spool and cuts it back to the events appended in full.
+    async fn roll_back(&mut self) {
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+        {
+            Ok(file) => self.file = file,
+            Err(e) => {
+                warn!("{}", spool_error("reopen", &self.path, e));
+                return;
+            }
+        }
+
+        let truncated = match self.file.metadata().await {
+            Ok(metadata) if metadata.len() > self.len => self.file.set_len(self.len).await,
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = truncated {
+            warn!("{}", spool_error("truncate", &self.path, e));
+        }
+    }
+
+    /// Drops every spooled event, once they have all been committed.
+    pub async fn truncate(&mut self) -> Result<(), Error> {
+        if self.len == 0 {
+            return Ok(());
+        }
+
+        self.file
+            .set_len(0)
+            .await
+            .map_err(|e| spool_error("truncate", &self.path, e))?;
+        self.len = 0;
+        Ok(())
+    }
+
+    /// Drops the first `count` spooled events, once every sink has written them, by rewriting
+    /// the rest to a new file that replaces the spool. Lines that are not events are dropped
+    /// as well.
+    pub async fn drop_first(&mut self, count: usize) -> Result<(), Error> {
+        let file = File::open(&self.path)
+            .await
+            .map_err(|e| spool_error("open", &self.path, e))?;
+        let mut lines = BufReader::new(file).lines();
+        let mut rest = String::new();
+        let mut skipped = 0;
+
+        while let Some(line) = lines
+            .next_line()
+            .await
+            .map_err(|e| spool_error("read", &self.path, e))?
+        {
+            if serde_json::from_str::<Event>(&line).is_err() {
+                continue;
+            }
+            if skipped < count {
+                skipped += 1;
+                continue;
+            }
+            rest.push_str(&line);
+            rest.push('\n');
+        }
+
+        let path = self.path.with_extension("tmp");
+        let mut file = File::create(&path)
+            .await
+            .map_err(|e| spool_error("create", &path, e))?;
+        file.write_all(rest.as_bytes())
+            .await
+            .map_err(|e| spool_error("write", &path, e))?;
+        file.sync_data()
+            .await
+            .map_err(|e| spool_error("sync", &path, e))?;
+        rename(&path, &self.path)
+            .await
+            .map_err(|e| spool_error("replace", &self.path, e))?;
+
+        self.file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| spool_error("open", &self.path, e))?;
+        self.len = rest.len() as u64;
+        Ok(())
+    }
+}
+
+/// The length of the file at `path`, `size` bytes long, up to and including its last newline.
+async fn whole_lines_len(path: &Path, size: u64) -> std::io::Result<u64> {
+    let mut file = File::open(path).await?;
+    let mut buffer = vec![0; 4096];
+    let mut end = size;
+    while end > 0 {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(chunk).await?;
+        if let Some(newline) = chunk.iter().rposition(|byte| *byte == b'\n') {
+            return Ok(start + newline as u64 + 1);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+fn spool_error(action: &str, path: &Path, error: std::io::Error) -> Error {
+    Error::Other(format!(
+        "Failed to {} spool {}: {}",
+        action,
+        path.display(),
+        error
+    ))
+}
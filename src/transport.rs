@@ -0,0 +1,157 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use either::Either;
+use futures_util::future::ready;
+use futures_util::sink::Sink;
+use futures_util::stream::{self, FusedStream};
+use futures_util::{SinkExt, StreamExt, TryStreamExt};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio_stream::wrappers::LinesStream;
+use tokio_util::codec::{BytesCodec, FramedWrite};
+use twitch_irc::message::{AsRawIRC, IRCMessage, IRCParseError};
+use twitch_irc::transport::tcp::{MakeConnection, TCPTransportConnectError};
+use twitch_irc::transport::Transport;
+
+const MEMBERSHIP_CAPABILITY: &str = "twitch.tv/membership";
+
+/// Tag under which `LoggerTransport` attaches the line each incoming message was parsed from.
+pub const RAW_LINE_TAG: &str = "twitch-logger/raw";
+
+/// Tag under which `LoggerTransport` attaches the id of the connection a message came from.
+pub const CONNECTION_TAG: &str = "twitch-logger/connection";
+
+/// Command of the message `LoggerTransport` adds once a connection's incoming stream ends, with
+/// the reason as its only parameter. twitch-irc forwards it like any unknown command.
+pub const DISCONNECTED_COMMAND: &str = "TWITCH-LOGGER-DISCONNECTED";
+
+static NEXT_CONNECTION: AtomicU64 = AtomicU64::new(1);
+
+/// A plain-IRC transport like twitch-irc's `TCPTransport`, with a few additions: every
+/// connection also requests the `twitch.tv/membership` capability, which twitch-irc does not
+/// ask for on its own, every incoming message keeps its exact raw line in `RAW_LINE_TAG` and
+/// its connection in `CONNECTION_TAG`, and a `DISCONNECTED_COMMAND` message marks the end of
+/// each connection, which twitch-irc otherwise handles silently.
+pub struct LoggerTransport<C: MakeConnection> {
+    incoming: <Self as Transport>::Incoming,
+    outgoing: <Self as Transport>::Outgoing,
+    connection: PhantomData<fn() -> C>,
+}
+
+#[async_trait]
+impl<C: MakeConnection> Transport for LoggerTransport<C> {
+    type ConnectError = TCPTransportConnectError;
+    type IncomingError = std::io::Error;
+    type OutgoingError = std::io::Error;
+
+    type Incoming = Box<
+        dyn FusedStream<Item = Result<IRCMessage, Either<std::io::Error, IRCParseError>>>
+            + Unpin
+            + Send
+            + Sync,
+    >;
+    type Outgoing = Box<dyn Sink<IRCMessage, Error = Self::OutgoingError> + Unpin + Send + Sync>;
+
+    async fn new() -> Result<Self, Self::ConnectError> {
+        let socket = C::new_socket().await?;
+        let (read_half, write_half) = tokio::io::split(socket);
+
+        let connection = NEXT_CONNECTION.fetch_add(1, Ordering::Relaxed);
+
+        let incoming = LinesStream::new(BufReader::new(read_half).lines())
+            .try_filter(|line| ready(!line.is_empty()))
+            .map_err(Either::Left)
+            .and_then(move |line| {
+                let message = parse_line(line).map(|message| tag_connection(message, connection));
+                ready(message.map_err(Either::Right))
+            })
+            // twitch-irc closes the connection on the first error, so it is the last item read.
+            .flat_map(move |item| match item {
+                Ok(message) => stream::iter(vec![Ok(message)]),
+                Err(e) => stream::iter(vec![Ok(disconnected(connection, &e.to_string())), Err(e)]),
+            })
+            .chain(stream::once(ready(Ok(disconnected(
+                connection,
+                "connection closed by the server",
+            )))))
+            .fuse();
+
+        let outgoing =
+            FramedWrite::new(write_half, BytesCodec::new()).with(|message: IRCMessage| {
+                let mut line = request_membership(message).as_raw_irc();
+                line.push_str("\r\n");
+                ready(Ok(Bytes::from(line)))
+            });
+
+        Ok(Self {
+            incoming: Box::new(incoming),
+            outgoing: Box::new(outgoing),
+            connection: PhantomData,
+        })
+    }
+
+    fn split(self) -> (Self::Incoming, Self::Outgoing) {
+        (self.incoming, self.outgoing)
+    }
+}
+
+impl<C: MakeConnection> std::fmt::Debug for LoggerTransport<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoggerTransport").finish()
+    }
+}
+
+/// Returns the exact line `message` was received as, or a re-serialization of it if it did not
+/// come through `LoggerTransport`.
+pub fn raw_line(message: &IRCMessage) -> String {
+    match message.tags.0.get(RAW_LINE_TAG) {
+        Some(Some(line)) => line.clone(),
+        _ => message.as_raw_irc(),
+    }
+}
+
+/// Parses a raw IRC line, keeping the line itself in `RAW_LINE_TAG`.
+pub fn parse_line(line: String) -> Result<IRCMessage, IRCParseError> {
+    let mut message = IRCMessage::parse(&line)?;
+    message.tags.0.insert(RAW_LINE_TAG.to_string(), Some(line));
+    Ok(message)
+}
+
+/// Returns the id `LoggerTransport` gave the connection `message` came from.
+pub fn connection_id(message: &IRCMessage) -> Option<u64> {
+    match message.tags.0.get(CONNECTION_TAG) {
+        Some(Some(id)) => id.parse().ok(),
+        _ => None,
+    }
+}
+
+fn tag_connection(mut message: IRCMessage, connection: u64) -> IRCMessage {
+    message
+        .tags
+        .0
+        .insert(CONNECTION_TAG.to_string(), Some(connection.to_string()));
+    message
+}
+
+fn disconnected(connection: u64, reason: &str) -> IRCMessage {
+    let message =
+        IRCMessage::new_simple(DISCONNECTED_COMMAND.to_string(), vec![reason.to_string()]);
+    tag_connection(message, connection)
+}
+
+fn request_membership(mut message: IRCMessage) -> IRCMessage {
+    let is_cap_req =
+        message.command == "CAP" && message.params.first().map(String::as_str) == Some("REQ");
+
+    if is_cap_req {
+        if let Some(capabilities) = message.params.get_mut(1) {
+            if !capabilities.split(' ').any(|c| c == MEMBERSHIP_CAPABILITY) {
+                capabilities.push(' ');
+                capabilities.push_str(MEMBERSHIP_CAPABILITY);
+            }
+        }
+    }
+
+    message
+}
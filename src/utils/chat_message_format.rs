@@ -1,14 +1,26 @@
 use crate::entities::chat::ChatMessage;
+use crate::entities::event::Event;
+use crate::entities::gap::Gap;
+use crate::entities::lifecycle::Lifecycle;
+use crate::entities::moderation::ModerationEvent;
+use crate::entities::presence::Presence;
+use crate::entities::room_state::RoomState;
+use crate::entities::user_notice::UserNotice;
+use serde::{Deserialize, Serialize};
 
 pub trait ChatMessageFormatter {
     fn format(&self, message: ChatMessage) -> String;
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum ChatMessageFormat {
     #[default]
     Json,
     Simple,
+    /// The raw IRC line, as written by other raw-IRC loggers. Events logged without their raw
+    /// line fall back to `Json`.
+    Raw,
 }
 
 impl ChatMessageFormat {
@@ -16,11 +28,49 @@ impl ChatMessageFormat {
         match self {
             ChatMessageFormat::Simple => format_simple(message),
             ChatMessageFormat::Json => format_json(message),
+            ChatMessageFormat::Raw => match &message.raw {
+                Some(raw) => raw.to_string(),
+                None => format_json(message),
+            },
+        }
+    }
+
+    /// Chat lines keep the plain `ChatMessage` layout; other events are tagged with their `type`.
+    pub fn format_event(&self, event: &Event) -> String {
+        match (self, event) {
+            (ChatMessageFormat::Raw, event) => match event.raw() {
+                Some(raw) => raw.to_string(),
+                None => ChatMessageFormat::Json.format_event(event),
+            },
+            (_, Event::Chat(message)) => self.format(message),
+            (ChatMessageFormat::Simple, Event::UserNotice(notice)) => format_simple_notice(notice),
+            (ChatMessageFormat::Simple, Event::Moderation(event)) => {
+                format_simple_moderation(event)
+            }
+            (ChatMessageFormat::Simple, Event::RoomState(state)) => format_simple_room_state(state),
+            (ChatMessageFormat::Simple, Event::Presence(presence)) => {
+                format_simple_presence(presence)
+            }
+            (ChatMessageFormat::Simple, Event::Gap(gap)) => format_simple_gap(gap),
+            (ChatMessageFormat::Simple, Event::Lifecycle(lifecycle)) => {
+                format_simple_lifecycle(lifecycle)
+            }
+            (ChatMessageFormat::Json, event) => serde_json::to_string(event).unwrap(),
         }
     }
 }
 
 fn format_simple(message: &ChatMessage) -> String {
+    if message.is_action {
+        return format!(
+            "{} (#{}) * {} {}",
+            message.sent_at.format("%Y-%m-%d %H:%M:%S"),
+            message.channel,
+            message.username,
+            message.message
+        );
+    }
+
     format!(
         "{} (#{}) {}: {}",
         message.sent_at.format("%Y-%m-%d %H:%M:%S"),
@@ -30,6 +80,66 @@ fn format_simple(message: &ChatMessage) -> String {
     )
 }
 
+fn format_simple_notice(notice: &UserNotice) -> String {
+    let mut line = format!(
+        "{} (#{}) * {}",
+        notice.sent_at.format("%Y-%m-%d %H:%M:%S"),
+        notice.channel,
+        notice.system_message
+    );
+    if let Some(message) = &notice.message {
+        line.push_str(&format!(" {}: {}", notice.username, message));
+    }
+    line
+}
+
+fn format_simple_moderation(event: &ModerationEvent) -> String {
+    format!(
+        "{} (#{}) * {}",
+        event.sent_at.format("%Y-%m-%d %H:%M:%S"),
+        event.channel,
+        event.action
+    )
+}
+
+fn format_simple_room_state(state: &RoomState) -> String {
+    format!(
+        "{} (#{}) * room state: {}",
+        state.recorded_at.format("%Y-%m-%d %H:%M:%S"),
+        state.channel,
+        state.changes()
+    )
+}
+
+fn format_simple_presence(presence: &Presence) -> String {
+    format!(
+        "{} (#{}) * {} {}",
+        presence.recorded_at.format("%Y-%m-%d %H:%M:%S"),
+        presence.channel,
+        presence.username,
+        presence.action.name()
+    )
+}
+
+fn format_simple_gap(gap: &Gap) -> String {
+    format!(
+        "{} (#{}) * {} events dropped until {}",
+        gap.started_at.format("%Y-%m-%d %H:%M:%S"),
+        gap.channel,
+        gap.dropped,
+        gap.ended_at.format("%Y-%m-%d %H:%M:%S")
+    )
+}
+
+fn format_simple_lifecycle(lifecycle: &Lifecycle) -> String {
+    format!(
+        "{} (#{}) * {}",
+        lifecycle.recorded_at.format("%Y-%m-%d %H:%M:%S"),
+        lifecycle.channel,
+        lifecycle.event
+    )
+}
+
 fn format_json(message: &ChatMessage) -> String {
     serde_json::to_string(message).unwrap()
 }
@@ -43,14 +43,6 @@ impl EnvStorage {
         }
     }
 
-    fn load_env_file(&self) -> Result<(), Error> {
-        if let Some(path) = &self.env_file_path {
-            dotenv::from_path(path)
-                .map_err(|e| Error::MissingEnv(format!("Failed to load env file: {}", e)))?;
-        }
-        Ok(())
-    }
-
     pub fn get_env<T>(&self, key: &str) -> Result<T, Error>
     where
         T: std::str::FromStr,
@@ -1,2 +1,3 @@
 pub mod chat_message_format;
 pub mod env;
+pub mod table_name;
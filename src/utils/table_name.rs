@@ -0,0 +1,169 @@
+use crate::error::Error;
+use crate::logger::migrations::longest_suffix;
+use std::fmt::{Display, Formatter};
+
+/// Postgres truncates longer identifiers.
+const MAX_IDENTIFIER_LENGTH: usize = 63;
+
+/// A validated `table` or `schema.table` name, written as a quoted identifier.
+///
+/// Names are folded to lowercase, as Postgres does with unquoted names, so that tables created
+/// before names were quoted are still found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableName {
+    schema: Option<String>,
+    table: String,
+}
+
+impl TableName {
+    pub fn parse(name: &str) -> Result<Self, Error> {
+        let invalid =
+            |reason: String| Error::InvalidConfig(format!("db_table: {:?} {}", name, reason));
+
+        let (schema, table) = match name.split_once('.') {
+            Some((schema, table)) => (Some(schema), table),
+            None => (None, name),
+        };
+        if let Some(schema) = schema {
+            validate_identifier(schema, MAX_IDENTIFIER_LENGTH).map_err(invalid)?;
+        }
+        // Leaves room for the names of the tables and indexes created next to it.
+        validate_identifier(table, MAX_IDENTIFIER_LENGTH - longest_suffix()).map_err(invalid)?;
+
+        Ok(Self {
+            schema: schema.map(str::to_lowercase),
+            table: table.to_lowercase(),
+        })
+    }
+
+    /// The table next to this one named `{table}_{suffix}`.
+    pub fn with_suffix(&self, suffix: &str) -> Self {
+        Self {
+            schema: self.schema.clone(),
+            table: format!("{}_{}", self.table, suffix),
+        }
+    }
+
+    /// The schema the table is in, if one was given.
+    pub fn schema(&self) -> Option<&str> {
+        self.schema.as_deref()
+    }
+
+    /// The quoted table name without its schema, for naming indexes and constraints.
+    pub fn unqualified(&self) -> String {
+        quote(&self.table)
+    }
+}
+
+impl Display for TableName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(schema) = &self.schema {
+            write!(f, "{}.", quote(schema))?;
+        }
+        write!(f, "{}", quote(&self.table))
+    }
+}
+
+fn validate_identifier(identifier: &str, max_length: usize) -> Result<(), String> {
+    let mut chars = identifier.chars();
+    match chars.next() {
+        None => return Err("has an empty name".to_string()),
+        Some(c) if !(c.is_ascii_alphabetic() || c == '_') => {
+            return Err("must start with a letter or underscore".to_string())
+        }
+        _ => {}
+    }
+    if !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err("may only contain letters, digits, underscores and one '.'".to_string());
+    }
+    if identifier.len() > max_length {
+        return Err(format!("is longer than {} characters", max_length));
+    }
+    Ok(())
+}
+
+/// Only ever called on validated identifiers, which cannot contain quotes.
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rejection(name: &str) -> String {
+        match TableName::parse(name) {
+            Err(Error::InvalidConfig(message)) => message,
+            result => panic!("expected {:?} to be rejected, got {:?}", name, result),
+        }
+    }
+
+    #[test]
+    fn quotes_and_folds_names() {
+        let table = TableName::parse("Chat").unwrap();
+        assert_eq!(table.schema(), None);
+        assert_eq!(table.to_string(), "\"chat\"");
+        assert_eq!(
+            table.with_suffix("moderation").to_string(),
+            "\"chat_moderation\""
+        );
+    }
+
+    #[test]
+    fn parses_schema_qualified_names() {
+        let table = TableName::parse("Logs.twitch_chat").unwrap();
+        assert_eq!(table.schema(), Some("logs"));
+        assert_eq!(table.to_string(), "\"logs\".\"twitch_chat\"");
+        assert_eq!(table.unqualified(), "\"twitch_chat\"");
+        assert_eq!(
+            table.with_suffix("gaps").to_string(),
+            "\"logs\".\"twitch_chat_gaps\""
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        for (name, reason) in [
+            ("", "has an empty name"),
+            ("logs.", "has an empty name"),
+            (".chat", "has an empty name"),
+            ("1chat", "must start with a letter or underscore"),
+            ("chat-log", "may only contain"),
+            ("chat\"; DROP TABLE chat; --", "may only contain"),
+            ("a.b.c", "may only contain"),
+        ] {
+            let message = rejection(name);
+            assert!(message.starts_with("db_table: "), "{}", message);
+            assert!(message.contains(reason), "{:?}: {}", name, message);
+        }
+    }
+
+    #[test]
+    fn limits_name_lengths() {
+        let schema = "s".repeat(MAX_IDENTIFIER_LENGTH);
+        assert!(TableName::parse(&format!("{}.chat", schema)).is_ok());
+        let message = rejection(&format!("{}s.chat", schema));
+        assert!(
+            message.contains("is longer than 63 characters"),
+            "{}",
+            message
+        );
+
+        let longest = MAX_IDENTIFIER_LENGTH - longest_suffix();
+        let message = rejection(&"c".repeat(longest + 1));
+        let expected = format!("is longer than {} characters", longest);
+        assert!(message.contains(&expected), "{}", message);
+    }
+
+    #[test]
+    fn leaves_room_for_the_longest_suffix() {
+        let suffix = "lifecycle_channel_event_recorded_at_key";
+        assert_eq!(longest_suffix(), suffix.len() + 1);
+
+        let longest = "a".repeat(MAX_IDENTIFIER_LENGTH - longest_suffix());
+        let table = TableName::parse(&longest).unwrap();
+        let index = table.with_suffix(suffix).unqualified();
+        assert_eq!(index.trim_matches('"').len(), MAX_IDENTIFIER_LENGTH);
+        assert!(TableName::parse(&format!("{}a", longest)).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::{Method, Request, StatusCode};
+use axum::Router;
+use common::{RecordingSink, PRIVMSG};
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::time::sleep;
+use tower::ServiceExt;
+use twitch_logger::admin::AdminApi;
+use twitch_logger::client::ClientHandle;
+use twitch_logger::config::Config;
+use twitch_logger::handler::MessageHandler;
+use twitch_logger::queue::{EventQueue, EventSender};
+use twitch_logger::replay::parse_replay_line;
+
+/// The admin router of a running handler that writes to `sink`, and the client's end of its
+/// queue.
+fn start(client: ClientHandle, sink: RecordingSink) -> (Router, EventSender) {
+    let (sender, rx) = EventQueue::new(16).start().unwrap();
+    let mut handler = MessageHandler::new(&Config::default(), rx).with_sink("test", Box::new(sink));
+    let api = AdminApi::new(client, handler.handle(), sender.handle());
+    tokio::spawn(async move { handler.run().await });
+    (api.router(), sender)
+}
+
+/// Sends `method uri` to `router`. Returns the status and the JSON body, or `null` if there is
+/// none.
+async fn call(router: &Router, method: Method, uri: &str) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .body(Body::empty())
+        .unwrap();
+    let response = router.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    if body.is_empty() {
+        return (status, Value::Null);
+    }
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn joins_and_parts_channels() {
+    let client = ClientHandle::default();
+    let (router, _sender) = start(client.clone(), RecordingSink::default());
+
+    assert_eq!(call(&router, Method::GET, "/channels").await.1, json!([]));
+
+    let join = |channel| format!("/channels/{}", channel);
+    assert_eq!(
+        call(&router, Method::PUT, &join("Forsen")).await.0,
+        StatusCode::CREATED
+    );
+    assert_eq!(
+        call(&router, Method::PUT, &join("forsen")).await.0,
+        StatusCode::OK
+    );
+    assert_eq!(
+        call(&router, Method::PUT, &join("xqc")).await.0,
+        StatusCode::CREATED
+    );
+    assert_eq!(client.channels(), ["forsen", "xqc"]);
+    assert_eq!(
+        call(&router, Method::GET, "/channels").await.1,
+        json!(["forsen", "xqc"])
+    );
+
+    assert_eq!(
+        call(&router, Method::DELETE, &join("forsen")).await.0,
+        StatusCode::OK
+    );
+    assert_eq!(
+        call(&router, Method::DELETE, &join("forsen")).await.0,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(client.channels(), ["xqc"]);
+
+    let (status, body) = call(&router, Method::PUT, &join("not-a-login")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("not-a-login"));
+    assert_eq!(client.channels(), ["xqc"]);
+}
+
+#[tokio::test]
+async fn flushes_the_handler() {
+    let sink = RecordingSink::default();
+    let (router, sender) = start(ClientHandle::default(), sink.clone());
+
+    sender
+        .send(parse_replay_line(PRIVMSG).unwrap().unwrap())
+        .await
+        .unwrap();
+    // Wait until the handler has taken the event off the queue, so the flush includes it.
+    while call(&router, Method::GET, "/stats").await.1["forsen"]["received"] != 1 {
+        sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(call(&router, Method::GET, "/queue").await.1["queued"], 0);
+
+    let (status, body) = call(&router, Method::POST, "/flush").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, json!({ "written": 1 }));
+    assert_eq!(sink.written(), 1);
+
+    let stats = call(&router, Method::GET, "/stats").await.1;
+    assert_eq!(stats["forsen"]["written"]["test"], 1);
+}
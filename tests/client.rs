@@ -0,0 +1,267 @@
+mod common;
+
+use common::{
+    next_event, next_lifecycle, remaining_events, start_client, FakeTwitchServer, TestClient,
+    PRIVMSG,
+};
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+use tokio::time::{sleep, timeout};
+use twitch_logger::credentials::Credentials;
+use twitch_logger::entities::event::Event;
+use twitch_logger::entities::lifecycle::LifecycleKind;
+use twitch_logger::entities::moderation::ModerationAction;
+use twitch_logger::entities::presence::PresenceAction;
+use twitch_logger::entities::user_notice::UserNoticeKind;
+use twitch_logger::queue::EventQueue;
+use twitch_logger::utils::chat_message_format::ChatMessageFormat;
+
+const USERNOTICE: &str = "@badge-info=subscriber/5;badges=subscriber/3;color=;display-name=Bob;emotes=;id=6f2b7cd5-d0fa-4a5c-8d4a-5b7d3a1f44f0;login=bob;msg-id=resub;msg-param-cumulative-months=5;msg-param-should-share-streak=0;msg-param-sub-plan-name=Channel\\sSubscription;msg-param-sub-plan=1000;room-id=11148817;system-msg=Bob\\ssubscribed\\sat\\sTier\\s1.;tmi-sent-ts=1680372001000;user-id=40286300 :tmi.twitch.tv USERNOTICE #forsen :still here";
+const CLEARCHAT: &str = "@ban-duration=600;room-id=11148817;target-user-id=22484632;tmi-sent-ts=1680372002000 :tmi.twitch.tv CLEARCHAT #forsen :alice";
+
+endpoint!(ScriptedLines);
+
+#[tokio::test]
+async fn forwards_scripted_lines() {
+    let server = FakeTwitchServer::start::<ScriptedLines>().await;
+    let (_, mut events) = start_client::<ScriptedLines>(&["forsen"], true);
+
+    let mut connection = server.accept().await;
+    assert!(connection
+        .capabilities
+        .iter()
+        .any(|c| c == "twitch.tv/membership"));
+    connection.expect_join("forsen").await;
+
+    match next_event(&mut events).await {
+        Event::Presence(presence) => {
+            assert_eq!(presence.username, connection.nick);
+            assert!(matches!(presence.action, PresenceAction::Join));
+        }
+        event => panic!("expected our own join, got {:?}", event),
+    }
+
+    for line in [PRIVMSG, USERNOTICE, CLEARCHAT] {
+        connection.send(line).await;
+    }
+
+    let chat = next_event(&mut events).await;
+    assert_eq!(ChatMessageFormat::Raw.format_event(&chat), PRIVMSG);
+    match chat {
+        Event::Chat(message) => {
+            assert_eq!(message.channel, "forsen");
+            assert_eq!(message.username, "alice");
+            assert_eq!(message.message, "hello there");
+            assert_eq!(message.color.as_deref(), Some("#FF0000"));
+        }
+        event => panic!("expected a chat message, got {:?}", event),
+    }
+
+    let notice = next_event(&mut events).await;
+    assert_eq!(ChatMessageFormat::Raw.format_event(&notice), USERNOTICE);
+    match notice {
+        Event::UserNotice(notice) => {
+            assert_eq!(notice.username, "bob");
+            assert_eq!(notice.message.as_deref(), Some("still here"));
+            assert!(matches!(
+                notice.event,
+                UserNoticeKind::SubOrResub {
+                    is_resub: true,
+                    cumulative_months: 5,
+                    ..
+                }
+            ));
+        }
+        event => panic!("expected a user notice, got {:?}", event),
+    }
+
+    match next_event(&mut events).await {
+        Event::Moderation(event) => {
+            assert_eq!(event.channel, "forsen");
+            assert!(matches!(
+                event.action,
+                ModerationAction::Timeout {
+                    duration_seconds: 600,
+                    ..
+                }
+            ));
+            assert_eq!(event.action.target_login(), Some("alice"));
+        }
+        event => panic!("expected a timeout, got {:?}", event),
+    }
+}
+
+endpoint!(RawLinesDropped);
+
+#[tokio::test]
+async fn drops_raw_lines_unless_stored() {
+    let server = FakeTwitchServer::start::<RawLinesDropped>().await;
+    let (_, mut events) = start_client::<RawLinesDropped>(&["forsen"], false);
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+    connection.send(PRIVMSG).await;
+
+    next_event(&mut events).await;
+    let chat = next_event(&mut events).await;
+    assert!(matches!(chat, Event::Chat(_)));
+    assert_eq!(chat.raw(), None);
+}
+
+endpoint!(PingPong);
+
+#[tokio::test]
+async fn answers_server_ping() {
+    let server = FakeTwitchServer::start::<PingPong>().await;
+    let _client = start_client::<PingPong>(&["forsen"], false);
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+    connection.send("PING :tmi.twitch.tv").await;
+
+    assert_eq!(connection.next_line().await, "PONG tmi.twitch.tv");
+}
+
+endpoint!(Reconnect);
+
+#[tokio::test]
+async fn rejoins_after_reconnect() {
+    let server = FakeTwitchServer::start::<Reconnect>().await;
+    let (_, mut events) = start_client::<Reconnect>(&["forsen", "pajlada"], false);
+
+    let mut connection = server.accept().await;
+    let mut joins = vec![connection.next_line().await, connection.next_line().await];
+    joins.sort();
+    assert_eq!(joins, ["JOIN #forsen", "JOIN #pajlada"]);
+
+    connection.send(":tmi.twitch.tv RECONNECT").await;
+
+    let mut connection = server.accept().await;
+    let mut joins = vec![connection.next_line().await, connection.next_line().await];
+    joins.sort();
+    assert_eq!(joins, ["JOIN #forsen", "JOIN #pajlada"]);
+
+    connection.send(PRIVMSG).await;
+    match next_event(&mut events).await {
+        Event::Chat(message) => assert_eq!(message.message, "hello there"),
+        event => panic!("expected a chat message, got {:?}", event),
+    }
+}
+
+endpoint!(RuntimeChannels);
+
+#[tokio::test]
+async fn joins_and_parts_at_runtime() {
+    let server = FakeTwitchServer::start::<RuntimeChannels>().await;
+    let (handle, _events) = start_client::<RuntimeChannels>(&["forsen"], false);
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+
+    assert!(handle.join("#Pajlada").unwrap());
+    assert!(!handle.join("pajlada").unwrap());
+    connection.expect_join("pajlada").await;
+
+    assert!(handle.part("forsen").unwrap());
+    assert_eq!(connection.next_line().await, "PART #forsen");
+    assert_eq!(handle.channels(), ["pajlada"]);
+    assert!(handle.join("not a channel").is_err());
+
+    connection.send(":tmi.twitch.tv RECONNECT").await;
+
+    let mut connection = server.accept().await;
+    connection.expect_join("pajlada").await;
+    connection.send("PING :tmi.twitch.tv").await;
+    assert_eq!(connection.next_line().await, "PONG tmi.twitch.tv");
+}
+
+endpoint!(Stop);
+
+#[tokio::test]
+async fn parts_and_closes_on_stop() {
+    let server = FakeTwitchServer::start::<Stop>().await;
+    let (handle, mut events) = start_client::<Stop>(&["forsen"], false);
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+    next_event(&mut events).await;
+    connection.send(PRIVMSG).await;
+
+    handle.stop();
+    assert_eq!(connection.next_line().await, "PART #forsen");
+
+    let remaining = remaining_events(&mut events).await;
+    assert!(remaining
+        .iter()
+        .all(|event| matches!(event, Event::Chat(_))));
+}
+
+endpoint!(Lifecycle);
+
+#[tokio::test]
+async fn logs_connection_lifecycle() {
+    let server = FakeTwitchServer::start::<Lifecycle>().await;
+    let (_, mut events) = start_client::<Lifecycle>(&["forsen"], false);
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+    assert_eq!(next_kind(&mut events).await, LifecycleKind::Connected);
+    assert_eq!(next_kind(&mut events).await, LifecycleKind::Joined);
+
+    connection.send(":tmi.twitch.tv RECONNECT").await;
+    assert_eq!(
+        next_kind(&mut events).await,
+        LifecycleKind::Disconnected {
+            reason: "reconnect requested by Twitch".to_string()
+        }
+    );
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+    assert_eq!(next_kind(&mut events).await, LifecycleKind::Reconnected);
+    assert_eq!(next_kind(&mut events).await, LifecycleKind::Joined);
+
+    drop(connection);
+    assert_eq!(
+        next_kind(&mut events).await,
+        LifecycleKind::Disconnected {
+            reason: "connection closed by the server".to_string()
+        }
+    );
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+    assert_eq!(next_kind(&mut events).await, LifecycleKind::Reconnected);
+}
+
+endpoint!(HandlerStopped);
+
+#[tokio::test]
+async fn returns_once_the_handler_stops() {
+    let server = FakeTwitchServer::start::<HandlerStopped>().await;
+    let channels = ["forsen".to_string()].into();
+    let mut client = TestClient::<HandlerStopped>::new(channels, Credentials::anonymous(), false);
+    let (tx, events) = EventQueue::new(1024).start().unwrap();
+    let started = tokio::spawn(async move { client.start(tx).await });
+    drop(events);
+
+    let mut connection = server.accept().await;
+    connection.expect_join("forsen").await;
+
+    // Give the queue a turn to find out the handler is gone, then make the client report a
+    // disconnect.
+    sleep(Duration::from_millis(100)).await;
+    connection.send(":tmi.twitch.tv RECONNECT").await;
+
+    timeout(Duration::from_secs(5), started)
+        .await
+        .expect("client kept running")
+        .unwrap()
+        .unwrap();
+}
+
+async fn next_kind(events: &mut Receiver<Event>) -> LifecycleKind {
+    let lifecycle = next_lifecycle(events).await;
+    assert_eq!(lifecycle.channel, "forsen");
+    lifecycle.event
+}
@@ -0,0 +1,265 @@
+//! A fake Twitch IRC server that a real `Client` can connect to, and the fixtures the tests
+//! share.
+//!
+//! Each test declares an endpoint with `endpoint!`, starts a `FakeTwitchServer` on it and builds
+//! a `Client<LoggerTransport<PlainConnection<Endpoint>>>` with anonymous credentials. The test then
+//! drives the connection step by step: accept it, answer the login, confirm joins and feed
+//! scripted lines, while collecting the events the client forwards.
+
+#![allow(dead_code)]
+
+use async_trait::async_trait;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::sync::OnceLock;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::Receiver;
+use tokio::time::timeout;
+use twitch_irc::transport::tcp::{MakeConnection, TCPTransportConnectError};
+use twitch_logger::client::{Client, ClientHandle};
+use twitch_logger::credentials::Credentials;
+use twitch_logger::entities::event::Event;
+use twitch_logger::entities::lifecycle::Lifecycle;
+use twitch_logger::error::Error;
+use twitch_logger::logger::sink::Sink;
+use twitch_logger::queue::EventQueue;
+use twitch_logger::transport::LoggerTransport;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const PRIVMSG: &str = "@badge-info=;badges=;color=#FF0000;display-name=Alice;emotes=;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;room-id=11148817;tmi-sent-ts=1680372000000;user-id=22484632 :alice!alice@alice.tmi.twitch.tv PRIVMSG #forsen :hello there";
+
+/// Records what it is given, and fails while `failing` is set.
+#[derive(Clone, Default)]
+pub struct RecordingSink {
+    pub written: Arc<Mutex<Vec<Event>>>,
+    pub failing: Arc<Mutex<bool>>,
+    pub shut_down: Arc<Mutex<bool>>,
+    /// How many events had been written when the sink was started.
+    pub started_after: Arc<Mutex<Option<usize>>>,
+}
+
+impl RecordingSink {
+    pub fn written(&self) -> usize {
+        self.written.lock().unwrap().len()
+    }
+
+    pub fn set_failing(&self, failing: bool) {
+        *self.failing.lock().unwrap() = failing;
+    }
+}
+
+#[async_trait]
+impl Sink for RecordingSink {
+    async fn write(&mut self, events: &[Event]) -> Result<(), Error> {
+        if *self.failing.lock().unwrap() {
+            return Err(Error::Other("sink is down".to_string()));
+        }
+        self.written.lock().unwrap().extend(events.iter().cloned());
+        Ok(())
+    }
+
+    async fn start(&mut self) -> Result<(), Error> {
+        *self.started_after.lock().unwrap() = Some(self.written());
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Error> {
+        *self.shut_down.lock().unwrap() = true;
+        Ok(())
+    }
+}
+
+/// The address a `PlainConnection` connects to. Declared per test with `endpoint!`, because
+/// twitch-irc creates connections without any arguments.
+pub trait Endpoint: 'static {
+    fn address() -> &'static OnceLock<SocketAddr>;
+}
+
+#[macro_export]
+macro_rules! endpoint {
+    ($name:ident) => {
+        struct $name;
+
+        impl $crate::common::Endpoint for $name {
+            fn address() -> &'static std::sync::OnceLock<std::net::SocketAddr> {
+                static ADDRESS: std::sync::OnceLock<std::net::SocketAddr> =
+                    std::sync::OnceLock::new();
+                &ADDRESS
+            }
+        }
+    };
+}
+
+/// Plain-text TCP connections to the `FakeTwitchServer` listening on `E`.
+pub struct PlainConnection<E: Endpoint> {
+    endpoint: PhantomData<fn() -> E>,
+}
+
+#[async_trait]
+impl<E: Endpoint> MakeConnection for PlainConnection<E> {
+    type Socket = TcpStream;
+
+    async fn new_socket() -> Result<Self::Socket, TCPTransportConnectError> {
+        let address = E::address().get().expect("fake server is not running");
+        Ok(TcpStream::connect(address).await?)
+    }
+}
+
+pub type TestClient<E> = Client<LoggerTransport<PlainConnection<E>>>;
+
+/// Starts an anonymous client for `channels` and returns its handle and the events it forwards.
+pub fn start_client<E: Endpoint>(
+    channels: &[&str],
+    store_raw: bool,
+) -> (ClientHandle, Receiver<Event>) {
+    let channels = channels.iter().map(|c| c.to_string()).collect();
+    let mut client = TestClient::<E>::new(channels, Credentials::anonymous(), store_raw);
+    let handle = client.handle();
+    let (tx, rx) = EventQueue::new(1024).start().unwrap();
+    tokio::spawn(async move { client.start(tx).await.unwrap() });
+    (handle, rx)
+}
+
+/// Waits for the next event the client forwards, other than lifecycle events.
+pub async fn next_event(events: &mut Receiver<Event>) -> Event {
+    loop {
+        match next_any(events).await {
+            Event::Lifecycle(_) => continue,
+            event => return event,
+        }
+    }
+}
+
+/// Waits for the next lifecycle event the client forwards, skipping any other.
+pub async fn next_lifecycle(events: &mut Receiver<Event>) -> Lifecycle {
+    loop {
+        if let Event::Lifecycle(lifecycle) = next_any(events).await {
+            return lifecycle;
+        }
+    }
+}
+
+async fn next_any(events: &mut Receiver<Event>) -> Event {
+    timeout(TIMEOUT, events.recv())
+        .await
+        .expect("timed out waiting for an event")
+        .expect("client stopped")
+}
+
+/// Waits for the client to drop its sender. Returns the events forwarded until then, other than
+/// lifecycle events.
+pub async fn remaining_events(events: &mut Receiver<Event>) -> Vec<Event> {
+    let mut remaining = vec![];
+    while let Some(event) = timeout(TIMEOUT, events.recv())
+        .await
+        .expect("timed out waiting for the client to stop")
+    {
+        if !matches!(event, Event::Lifecycle(_)) {
+            remaining.push(event);
+        }
+    }
+    remaining
+}
+
+pub struct FakeTwitchServer {
+    listener: TcpListener,
+}
+
+impl FakeTwitchServer {
+    pub async fn start<E: Endpoint>() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        E::address()
+            .set(listener.local_addr().unwrap())
+            .expect("endpoint is already in use");
+        Self { listener }
+    }
+
+    /// Accepts the next connection and answers its login.
+    pub async fn accept(&self) -> FakeConnection {
+        let (socket, _) = timeout(TIMEOUT, self.listener.accept())
+            .await
+            .expect("timed out waiting for a connection")
+            .unwrap();
+        let (read_half, write_half) = socket.into_split();
+        let mut connection = FakeConnection {
+            lines: BufReader::new(read_half).lines(),
+            writer: write_half,
+            capabilities: vec![],
+            nick: String::new(),
+        };
+        connection.login().await;
+        connection
+    }
+}
+
+/// One client connection, as seen by the server.
+pub struct FakeConnection {
+    lines: Lines<BufReader<OwnedReadHalf>>,
+    writer: OwnedWriteHalf,
+    /// The capabilities requested with `CAP REQ`.
+    pub capabilities: Vec<String>,
+    pub nick: String,
+}
+
+impl FakeConnection {
+    async fn login(&mut self) {
+        loop {
+            let line = self.next_line().await;
+            let mut params = line.split(' ');
+            match params.next() {
+                Some("CAP") if params.next() == Some("REQ") => {
+                    let capabilities = params.collect::<Vec<_>>().join(" ");
+                    let capabilities = capabilities.trim_start_matches(':');
+                    self.capabilities = capabilities.split(' ').map(String::from).collect();
+                    self.send(&format!(":tmi.twitch.tv CAP * ACK :{}", capabilities))
+                        .await;
+                }
+                Some("PASS") => {}
+                Some("NICK") => {
+                    self.nick = params.next().unwrap_or_default().to_string();
+                    let welcome = format!(":tmi.twitch.tv 001 {} :Welcome, GLHF!", self.nick);
+                    self.send(&welcome).await;
+                    return;
+                }
+                _ => panic!("unexpected line during login: {}", line),
+            }
+        }
+    }
+
+    /// Waits for the client to join `channel` and confirms the join.
+    pub async fn expect_join(&mut self, channel: &str) {
+        let join = format!("JOIN #{}", channel);
+        let line = self.next_line().await;
+        assert_eq!(line, join);
+
+        let nick = self.nick.clone();
+        self.send(&format!(":{0}!{0}@{0}.tmi.twitch.tv {1}", nick, join))
+            .await;
+    }
+
+    /// Waits for the next line from the client, answering any `PING` on the way.
+    pub async fn next_line(&mut self) -> String {
+        loop {
+            let line = timeout(TIMEOUT, self.lines.next_line())
+                .await
+                .expect("timed out waiting for the client")
+                .unwrap()
+                .expect("client closed the connection");
+
+            match line.strip_prefix("PING") {
+                Some(argument) => self.send(&format!("PONG{}", argument)).await,
+                None => return line,
+            }
+        }
+    }
+
+    pub async fn send(&mut self, line: &str) {
+        self.writer.write_all(line.as_bytes()).await.unwrap();
+        self.writer.write_all(b"\r\n").await.unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::fs;
+use twitch_logger::config::Config;
+use twitch_logger::error::Error;
+
+/// Loads `toml` as the config file.
+fn load(name: &str, toml: &str) -> Result<Config, Error> {
+    let path = std::env::temp_dir().join(format!(
+        "twitch-logger-config-{}-{}.toml",
+        name,
+        std::process::id()
+    ));
+    fs::write(&path, toml).unwrap();
+    let config = Config::new(Some(path.to_string_lossy().into_owned()));
+    fs::remove_file(&path).unwrap();
+    config
+}
+
+fn assert_invalid(result: Result<Config, Error>, key: &str) {
+    match result {
+        Err(Error::InvalidConfig(message)) => assert!(message.starts_with(key), "{}", message),
+        result => panic!("expected {} to be rejected, got {:?}", key, result),
+    }
+}
+
+#[test]
+fn reads_absent_sections_as_unset() {
+    let config = load("absent", "db_table = \"chat\"\n").unwrap();
+    assert!(config.filters.is_none());
+    assert!(config.sinks.is_none());
+    assert!(config.channels.is_none());
+    config.validate().unwrap();
+}
+
+#[test]
+fn only_serves_the_admin_api_on_loopback() {
+    for address in ["127.0.0.1:8080", "[::1]:8080"] {
+        let toml = format!("db_table = \"chat\"\nadmin_address = \"{}\"\n", address);
+        load("admin", &toml).unwrap().validate().unwrap();
+    }
+
+    for address in ["0.0.0.0:8080", "192.168.1.2:8080", "[::]:8080"] {
+        let toml = format!("db_table = \"chat\"\nadmin_address = \"{}\"\n", address);
+        let config = load("admin", &toml).unwrap();
+        assert!(config.admin_address.is_some());
+        assert_invalid(config.validate().map(|_| config), "admin_address");
+    }
+}
+
+#[test]
+fn rejects_malformed_filters() {
+    let config = load(
+        "filters",
+        "db_table = \"chat\"\n\n[[filter]]\ntype = \"include\"\npatern = \"hello\"\n",
+    );
+    assert_invalid(config, "filter");
+}
+
+#[test]
+fn rejects_malformed_sinks() {
+    let config = load(
+        "sinks",
+        "db_table = \"chat\"\n\n[sink.archive]\ntype = \"file\"\npath = \"chat.log\"\n\
+         max_batch_size = \"ten\"\n",
+    );
+    assert_invalid(config, "sink");
+
+    let config = load(
+        "sink-type",
+        "db_table = \"chat\"\n\n[sink.archive]\ntype = \"fiel\"\npath = \"chat.log\"\n",
+    );
+    assert_invalid(config, "sink");
+}
+
+#[test]
+fn rejects_malformed_channels() {
+    let config = load(
+        "channels",
+        "db_table = \"chat\"\n\n[channel.forsen]\nsinks = \"database\"\n",
+    );
+    assert_invalid(config, "channel");
+}
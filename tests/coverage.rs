@@ -0,0 +1,193 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use twitch_logger::coverage::{coverage, CoverageOptions, CoverageRecords, HoleReason, Interval};
+use twitch_logger::entities::gap::Gap;
+use twitch_logger::entities::lifecycle::{Lifecycle, LifecycleKind};
+
+fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2023, 4, day, hour, minute, 0).unwrap()
+}
+
+fn lifecycle(event: LifecycleKind, recorded_at: DateTime<Utc>) -> Lifecycle {
+    Lifecycle {
+        channel: "forsen".to_string(),
+        event,
+        recorded_at,
+    }
+}
+
+fn started(recorded_at: DateTime<Utc>) -> Lifecycle {
+    let version = "1.0.0".to_string();
+    lifecycle(LifecycleKind::Started { version }, recorded_at)
+}
+
+/// `per_minute` messages in every minute from `from` until `to`.
+fn chatter(
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    per_minute: u64,
+) -> Vec<(String, DateTime<Utc>, u64)> {
+    let mut activity = vec![];
+    let mut minute = from;
+    while minute < to {
+        activity.push(("forsen".to_string(), minute, per_minute));
+        minute += Duration::minutes(1);
+    }
+    activity
+}
+
+fn interval(start: DateTime<Utc>, end: DateTime<Utc>, inferred: bool) -> Interval {
+    Interval {
+        start,
+        end,
+        inferred,
+    }
+}
+
+#[test]
+fn covers_from_join_until_disconnect_or_crash() {
+    let records = CoverageRecords {
+        activity: [
+            chatter(at(1, 18, 0), at(1, 19, 0), 5),
+            chatter(at(1, 20, 30), at(1, 21, 0), 5),
+        ]
+        .concat(),
+        lifecycle: vec![
+            started(at(1, 17, 58)),
+            lifecycle(LifecycleKind::Connected, at(1, 17, 59)),
+            lifecycle(LifecycleKind::Joined, at(1, 17, 59)),
+            lifecycle(
+                LifecycleKind::Disconnected {
+                    reason: "connection closed by the server".to_string(),
+                },
+                at(1, 19, 30),
+            ),
+            lifecycle(LifecycleKind::Reconnected, at(1, 20, 0)),
+            lifecycle(LifecycleKind::Joined, at(1, 20, 0)),
+            // The run crashed after its last message, at 20:59.
+            started(at(1, 22, 0)),
+        ],
+        gaps: vec![],
+    };
+
+    let report = coverage(&records, &CoverageOptions::default());
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].channel, "forsen");
+    let day = &report[0].days[0];
+    assert_eq!(
+        day.intervals,
+        [
+            interval(at(1, 17, 59), at(1, 19, 30), false),
+            interval(at(1, 20, 0), at(1, 21, 0), false),
+        ]
+    );
+    assert_eq!(day.covered(), Duration::minutes(151));
+    assert!(day.holes.is_empty());
+}
+
+#[test]
+fn infers_intervals_before_lifecycle_events_and_splits_days() {
+    let records = CoverageRecords {
+        activity: [
+            chatter(at(1, 23, 0), at(2, 1, 0), 2),
+            chatter(at(2, 3, 0), at(2, 3, 10), 2),
+            chatter(at(2, 12, 0), at(2, 12, 30), 2),
+        ]
+        .concat(),
+        lifecycle: vec![
+            lifecycle(LifecycleKind::Joined, at(2, 11, 0)),
+            lifecycle(LifecycleKind::Parted, at(2, 13, 0)),
+        ],
+        gaps: vec![],
+    };
+
+    let report = coverage(&records, &CoverageOptions::default());
+    let days = &report[0].days;
+    assert_eq!(days.len(), 2);
+    assert_eq!(
+        days[0].intervals,
+        [interval(at(1, 23, 0), at(2, 0, 0), true)]
+    );
+    assert_eq!(
+        days[1].intervals,
+        [
+            interval(at(2, 0, 0), at(2, 1, 0), true),
+            interval(at(2, 3, 0), at(2, 3, 10), true),
+            interval(at(2, 11, 0), at(2, 13, 0), false),
+        ]
+    );
+    assert!(report[0].to_string().contains("23:00:00-24:00:00*"));
+}
+
+#[test]
+fn flags_silences_while_live_and_dropped_events() {
+    let mut gap = Gap::new("forsen".to_string(), at(1, 19, 40));
+    gap.record(at(1, 19, 41));
+    let records = CoverageRecords {
+        activity: [
+            chatter(at(1, 18, 0), at(1, 18, 30), 10),
+            // Twenty silent minutes in the middle of the stream.
+            chatter(at(1, 18, 50), at(1, 19, 0), 10),
+            // The stream ended at 19:00, and chat was quiet until a late message.
+            chatter(at(1, 20, 0), at(1, 20, 1), 1),
+        ]
+        .concat(),
+        lifecycle: vec![
+            lifecycle(LifecycleKind::Joined, at(1, 17, 0)),
+            lifecycle(
+                LifecycleKind::Stopped {
+                    version: "1.0.0".to_string(),
+                },
+                at(1, 21, 0),
+            ),
+        ],
+        gaps: vec![gap],
+    };
+
+    let report = coverage(&records, &CoverageOptions::default());
+    let holes = &report[0].days[0].holes;
+    assert_eq!(holes.len(), 2);
+    assert_eq!(
+        (holes[0].start, holes[0].end),
+        (at(1, 18, 30), at(1, 18, 50))
+    );
+    match holes[0].reason {
+        HoleReason::Silent {
+            rate_before,
+            rate_after,
+        } => assert_eq!((rate_before, rate_after), (10.0, 10.0)),
+        ref reason => panic!("expected a silence, got {:?}", reason),
+    }
+    assert!(matches!(holes[1].reason, HoleReason::Dropped { events: 1 }));
+}
+
+#[test]
+fn prints_the_date_of_holes_ending_after_midnight() {
+    let records = CoverageRecords {
+        activity: [
+            chatter(at(1, 23, 30), at(1, 23, 50), 10),
+            chatter(at(2, 0, 10), at(2, 0, 30), 10),
+        ]
+        .concat(),
+        lifecycle: vec![
+            lifecycle(LifecycleKind::Joined, at(1, 23, 0)),
+            lifecycle(LifecycleKind::Parted, at(2, 1, 0)),
+        ],
+        gaps: vec![],
+    };
+
+    let report = coverage(&records, &CoverageOptions::default());
+    let days = &report[0].days;
+    assert_eq!(
+        (days[0].holes[0].start, days[0].holes[0].end),
+        (at(1, 23, 50), at(2, 0, 10))
+    );
+    assert!(days[1].holes.is_empty());
+
+    let report = report[0].to_string();
+    assert!(report.contains("23:00:00-24:00:00"), "{}", report);
+    assert!(
+        report.contains("! 23:50:00-2023-04-02 00:10:00"),
+        "{}",
+        report
+    );
+}
@@ -0,0 +1,139 @@
+use chrono::Utc;
+use twitch_logger::config::Config;
+use twitch_logger::entities::chat::ChatMessage;
+use twitch_logger::entities::event::Event;
+use twitch_logger::entities::presence::{Presence, PresenceAction};
+use twitch_logger::filter::{FilterConfig, Pipeline};
+use twitch_logger::replay::parse_replay_line;
+
+const ACTION: &str = "@badge-info=;badges=;color=;display-name=Alice;emotes=;id=c0ffee;room-id=1;tmi-sent-ts=1680372000000;user-id=2 :alice!alice@alice.tmi.twitch.tv PRIVMSG #forsen :\u{1}ACTION waves\u{1}";
+
+fn pipeline(filters: Vec<FilterConfig>) -> Pipeline {
+    let config = Config {
+        filters: Some(filters),
+        ..Config::default()
+    };
+    Pipeline::try_from(&config).unwrap()
+}
+
+fn chat(username: &str, message: &str) -> Event {
+    Event::Chat(ChatMessage::new(
+        "forsen".to_string(),
+        username.to_string(),
+        message.to_string(),
+        Utc::now(),
+    ))
+}
+
+fn text(event: Option<Event>) -> Option<String> {
+    match event? {
+        Event::Chat(message) => Some(message.message),
+        _ => None,
+    }
+}
+
+#[test]
+fn includes_and_excludes_by_pattern() {
+    let pipeline = pipeline(vec![
+        FilterConfig::Include {
+            pattern: "(?i)forsen".to_string(),
+        },
+        FilterConfig::Exclude {
+            pattern: "^!".to_string(),
+        },
+    ]);
+
+    assert!(pipeline.apply(chat("a", "FORSEN hi")).is_some());
+    assert!(pipeline.apply(chat("a", "hello")).is_none());
+    assert!(pipeline.apply(chat("a", "!forsen")).is_none());
+}
+
+#[test]
+fn allows_and_denies_users() {
+    let allow = pipeline(vec![FilterConfig::AllowUsers {
+        users: vec!["Alice".to_string()],
+    }]);
+    let deny = pipeline(vec![FilterConfig::DenyUsers {
+        users: vec!["nightbot".to_string()],
+    }]);
+
+    assert!(allow.apply(chat("alice", "hi")).is_some());
+    assert!(allow.apply(chat("bob", "hi")).is_none());
+    assert!(deny.apply(chat("NightBot", "hi")).is_none());
+    assert!(deny.apply(chat("bob", "hi")).is_some());
+}
+
+#[test]
+fn strips_invisible_characters() {
+    let pipeline = pipeline(vec![FilterConfig::StripInvisible]);
+
+    assert_eq!(
+        text(pipeline.apply(chat("a", "he\u{200B}llo \u{E0000}"))).as_deref(),
+        Some("hello")
+    );
+    assert_eq!(
+        text(pipeline.apply(chat("a", "👨\u{200D}👩"))).as_deref(),
+        Some("👨\u{200D}👩")
+    );
+}
+
+#[test]
+fn truncates_on_character_boundaries() {
+    let pipeline = pipeline(vec![FilterConfig::Truncate { max_chars: 3 }]);
+
+    assert_eq!(
+        text(pipeline.apply(chat("a", "héllo"))).as_deref(),
+        Some("hél")
+    );
+    assert_eq!(text(pipeline.apply(chat("a", "hi"))).as_deref(), Some("hi"));
+}
+
+#[test]
+fn normalizes_actions() {
+    let pipeline = pipeline(vec![FilterConfig::NormalizeAction]);
+
+    for message in ["/me waves", "\u{1}ACTION waves\u{1}"] {
+        match pipeline.apply(chat("a", message)) {
+            Some(Event::Chat(message)) => {
+                assert_eq!(message.message, "waves");
+                assert!(message.is_action);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    match parse_replay_line(ACTION).unwrap() {
+        Some(Event::Chat(message)) => {
+            assert_eq!(message.message, "waves");
+            assert!(message.is_action);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn passes_other_events_through() {
+    let pipeline = pipeline(vec![FilterConfig::AllowUsers { users: vec![] }]);
+    let presence = Event::Presence(Presence {
+        channel: "forsen".to_string(),
+        username: "alice".to_string(),
+        action: PresenceAction::Join,
+        recorded_at: Utc::now(),
+        raw: None,
+    });
+
+    assert!(pipeline.apply(presence).is_some());
+    assert!(pipeline.apply(chat("alice", "hi")).is_none());
+}
+
+#[test]
+fn rejects_invalid_patterns() {
+    let config = Config {
+        filters: Some(vec![FilterConfig::Include {
+            pattern: "(".to_string(),
+        }]),
+        ..Config::default()
+    };
+
+    assert!(Pipeline::try_from(&config).is_err());
+}
@@ -0,0 +1,371 @@
+mod common;
+
+use chrono::Utc;
+use common::{RecordingSink, PRIVMSG};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc::channel;
+use tokio::time::sleep;
+use twitch_irc::message::Badge;
+use twitch_logger::config::{ChannelConfig, Config};
+use twitch_logger::entities::chat::{BadgeLevel, ChatMessage};
+use twitch_logger::entities::event::Event;
+use twitch_logger::entities::lifecycle::{Lifecycle, LifecycleKind};
+use twitch_logger::handler::{BatchPolicy, HandlerHandle, MessageHandler};
+use twitch_logger::replay::parse_replay_line;
+use twitch_logger::spool::Spool;
+
+fn chat() -> Event {
+    parse_replay_line(PRIVMSG).unwrap().unwrap()
+}
+
+/// Waits until the handler has taken `count` events off the queue, so a flush includes them.
+async fn wait_for_received(handle: &HandlerHandle, count: u64) {
+    while handle
+        .stats()
+        .get("forsen")
+        .map_or(0, |stats| stats.received)
+        < count
+    {
+        sleep(Duration::from_millis(5)).await;
+    }
+}
+
+#[tokio::test]
+async fn isolates_failing_sinks() {
+    let healthy = RecordingSink::default();
+    let flaky = RecordingSink::default();
+    flaky.set_failing(true);
+
+    let (tx, rx) = channel(16);
+    let mut handler = MessageHandler::new(&Config::default(), rx)
+        .with_sink("healthy", Box::new(healthy.clone()))
+        .with_sink("flaky", Box::new(flaky.clone()));
+    let handle = handler.handle();
+    let running = tokio::spawn(async move { handler.run().await });
+
+    tx.send(chat()).await.unwrap();
+    wait_for_received(&handle, 1).await;
+    assert!(handle.flush().await.is_err());
+    assert_eq!(healthy.written(), 1);
+    assert_eq!(flaky.written(), 0);
+
+    flaky.set_failing(false);
+    tx.send(chat()).await.unwrap();
+    wait_for_received(&handle, 2).await;
+    assert_eq!(handle.flush().await.unwrap(), 3);
+    assert_eq!(healthy.written(), 2);
+    assert_eq!(flaky.written(), 2);
+
+    let stats = handle.stats();
+    assert_eq!(stats["forsen"].received, 2);
+    assert_eq!(stats["forsen"].written["healthy"], 2);
+    assert_eq!(stats["forsen"].written["flaky"], 2);
+
+    drop(tx);
+    running.await.unwrap().unwrap();
+    assert!(*healthy.shut_down.lock().unwrap());
+    assert!(*flaky.shut_down.lock().unwrap());
+}
+
+fn chat_from(username: &str, message: &str, badges: &[&str]) -> Event {
+    let mut chat = ChatMessage::new(
+        "forsen".to_string(),
+        username.to_string(),
+        message.to_string(),
+        Utc::now(),
+    );
+    chat.badges = badges
+        .iter()
+        .map(|name| Badge {
+            name: name.to_string(),
+            version: "1".to_string(),
+        })
+        .collect();
+    Event::Chat(chat)
+}
+
+#[tokio::test]
+async fn applies_channel_settings() {
+    let routed = RecordingSink::default();
+    let other = RecordingSink::default();
+    let settings = ChannelConfig {
+        sinks: Some(vec!["routed".to_string()]),
+        ignored_users: Some(vec!["Nightbot".to_string()]),
+        log_commands: Some(false),
+        min_badge_level: Some(BadgeLevel::Subscriber),
+        non_chat_events: Some(false),
+    };
+    let config = Config {
+        channels: Some(HashMap::from([("#Forsen".to_string(), settings)])),
+        ..Config::default()
+    };
+
+    let (tx, rx) = channel(16);
+    let mut handler = MessageHandler::new(&config, rx)
+        .with_sink("routed", Box::new(routed.clone()))
+        .with_sink("other", Box::new(other.clone()));
+    let handle = handler.handle();
+    let running = tokio::spawn(async move { handler.run().await });
+
+    for event in [
+        chat_from("nightbot", "follow the channel", &["moderator"]),
+        chat_from("bob", "!uptime", &["subscriber"]),
+        chat_from("carol", "hello", &[]),
+        chat(),
+        chat_from("dave", "hello", &["founder"]),
+        chat_from("erin", "hi", &["vip", "subscriber"]),
+    ] {
+        tx.send(event).await.unwrap();
+    }
+    wait_for_received(&handle, 6).await;
+    handle.flush().await.unwrap();
+
+    let written = routed.written.lock().unwrap().clone();
+    let senders = written
+        .iter()
+        .map(|event| match event {
+            Event::Chat(message) => message.username.as_str(),
+            _ => "",
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(senders, ["dave", "erin"]);
+    assert_eq!(other.written(), 0);
+    assert_eq!(handle.stats()["forsen"].filtered, 4);
+
+    drop(tx);
+    running.await.unwrap().unwrap();
+}
+
+fn policy(max_events: usize, max_age: Duration) -> BatchPolicy {
+    BatchPolicy {
+        max_events,
+        max_age,
+        ..BatchPolicy::default()
+    }
+}
+
+/// Waits until `sink` has written `count` events, without asking the handler to flush.
+async fn wait_for_written(sink: &RecordingSink, count: usize) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while sink.written() < count {
+            sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("timed out waiting for a batch");
+}
+
+#[tokio::test]
+async fn writes_batches_when_full_or_due() {
+    let by_size = RecordingSink::default();
+    let by_age = RecordingSink::default();
+
+    let (tx, rx) = channel(16);
+    let mut handler = MessageHandler::new(&Config::default(), rx)
+        .with_batched_sink(
+            "by_size",
+            Box::new(by_size.clone()),
+            policy(2, Duration::from_secs(3600)),
+        )
+        .with_batched_sink(
+            "by_age",
+            Box::new(by_age.clone()),
+            policy(1000, Duration::from_millis(50)),
+        );
+    let handle = handler.handle();
+    let running = tokio::spawn(async move { handler.run().await });
+
+    tx.send(chat()).await.unwrap();
+    wait_for_written(&by_age, 1).await;
+    assert_eq!(by_size.written(), 0);
+
+    tx.send(chat()).await.unwrap();
+    wait_for_written(&by_size, 2).await;
+    wait_for_written(&by_age, 2).await;
+    assert_eq!(handle.stats()["forsen"].written["by_size"], 2);
+
+    drop(tx);
+    running.await.unwrap().unwrap();
+}
+
+/// Waits until the spool at `path` holds `count` events. The file is read directly, since
+/// opening a second `Spool` on it would cut off an append in progress.
+async fn wait_for_spooled(path: &Path, count: usize) {
+    let spooled = || async {
+        let lines = tokio::fs::read_to_string(path).await.unwrap();
+        lines.matches('\n').count()
+    };
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while spooled().await != count {
+            sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("timed out waiting for the spool");
+}
+
+#[tokio::test]
+async fn keeps_unwritten_events_in_the_spool() {
+    let path =
+        std::env::temp_dir().join(format!("twitch-logger-spool-{}.jsonl", std::process::id()));
+    let pairs = RecordingSink::default();
+    let triples = RecordingSink::default();
+
+    let (tx, rx) = channel(16);
+    let mut handler = MessageHandler::new(&Config::default(), rx)
+        .with_spool(Spool::open(path.clone()).await.unwrap(), vec![])
+        .with_batched_sink(
+            "pairs",
+            Box::new(pairs.clone()),
+            policy(2, Duration::from_secs(3600)),
+        )
+        .with_batched_sink(
+            "triples",
+            Box::new(triples.clone()),
+            policy(3, Duration::from_secs(3600)),
+        );
+    let running = tokio::spawn(async move { handler.run().await });
+
+    tx.send(chat()).await.unwrap();
+    tx.send(chat()).await.unwrap();
+    wait_for_written(&pairs, 2).await;
+    wait_for_spooled(&path, 2).await;
+
+    // Both sinks have written the first two events, but only one the third.
+    tx.send(chat()).await.unwrap();
+    wait_for_written(&triples, 3).await;
+    wait_for_spooled(&path, 1).await;
+
+    tx.send(chat()).await.unwrap();
+    wait_for_written(&pairs, 4).await;
+    wait_for_spooled(&path, 1).await;
+
+    drop(tx);
+    running.await.unwrap().unwrap();
+    wait_for_spooled(&path, 0).await;
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn starts_sinks_after_writing_recovered_events() {
+    let path = std::env::temp_dir().join(format!(
+        "twitch-logger-recover-{}.jsonl",
+        std::process::id()
+    ));
+    let mut spool = Spool::open(path.clone()).await.unwrap();
+    spool.append(&[chat(), chat()]).await.unwrap();
+    let pending = spool.pending().await.unwrap();
+    let sink = RecordingSink::default();
+
+    let (tx, rx) = channel(16);
+    let mut handler = MessageHandler::new(&Config::default(), rx)
+        .with_spool(spool, pending)
+        .with_batched_sink(
+            "sink",
+            Box::new(sink.clone()),
+            policy(100, Duration::from_secs(3600)),
+        );
+    let running = tokio::spawn(async move { handler.run().await });
+
+    tx.send(chat()).await.unwrap();
+    drop(tx);
+    running.await.unwrap().unwrap();
+    assert_eq!(*sink.started_after.lock().unwrap(), Some(2));
+    assert_eq!(sink.written(), 3);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn holds_events_until_the_spool_takes_them() {
+    let dir = std::env::temp_dir().join(format!("twitch-logger-full-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let link = dir.join("spool.jsonl");
+    let file = dir.join("spool-file.jsonl");
+    // Every write to the spool fails with "no space left on device" until the link is moved.
+    std::os::unix::fs::symlink("/dev/full", &link).unwrap();
+
+    let sink = RecordingSink::default();
+    let (tx, rx) = channel(16);
+    let mut handler = MessageHandler::new(&Config::default(), rx)
+        .with_spool(Spool::open(link.clone()).await.unwrap(), vec![])
+        .with_batched_sink(
+            "sink",
+            Box::new(sink.clone()),
+            policy(1, Duration::from_secs(3600)),
+        );
+    let handle = handler.handle();
+    let running = tokio::spawn(async move { handler.run().await });
+
+    tx.send(chat()).await.unwrap();
+    wait_for_received(&handle, 1).await;
+    tx.send(chat()).await.unwrap();
+    sleep(Duration::from_millis(300)).await;
+    assert_eq!(sink.written(), 0);
+    assert_eq!(handle.stats()["forsen"].received, 1);
+
+    std::fs::remove_file(&link).unwrap();
+    std::os::unix::fs::symlink(&file, &link).unwrap();
+    wait_for_written(&sink, 2).await;
+
+    drop(tx);
+    running.await.unwrap().unwrap();
+    wait_for_spooled(&file, 0).await;
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn logs_start_and_stop_for_each_channel() {
+    let sink = RecordingSink::default();
+    let config = Config {
+        channels: Some(HashMap::from([(
+            "forsen".to_string(),
+            ChannelConfig::default(),
+        )])),
+        ..Config::default()
+    };
+
+    let (tx, rx) = channel(16);
+    let mut handler = MessageHandler::new(&config, rx)
+        .with_sink("sink", Box::new(sink.clone()))
+        .with_lifecycle_events();
+    let running = tokio::spawn(async move { handler.run().await });
+
+    tx.send(Event::Lifecycle(Lifecycle::new(
+        "pajlada".to_string(),
+        LifecycleKind::Joined,
+    )))
+    .await
+    .unwrap();
+    tx.send(chat()).await.unwrap();
+    drop(tx);
+    running.await.unwrap().unwrap();
+
+    let written = sink.written.lock().unwrap();
+    let lifecycle = written
+        .iter()
+        .filter_map(|event| match event {
+            Event::Lifecycle(lifecycle) => {
+                Some((lifecycle.channel.as_str(), lifecycle.event.name()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        lifecycle,
+        [
+            ("forsen", "started"),
+            ("pajlada", "joined"),
+            ("forsen", "stopped"),
+            ("pajlada", "stopped"),
+        ]
+    );
+    match written.last() {
+        Some(Event::Lifecycle(lifecycle)) => {
+            assert_eq!(lifecycle.event.version(), Some(env!("CARGO_PKG_VERSION")))
+        }
+        event => panic!("expected a stop, got {:?}", event),
+    }
+}
@@ -0,0 +1,163 @@
+use chrono::Utc;
+use tokio::sync::mpsc::Receiver;
+use twitch_logger::entities::chat::ChatMessage;
+use twitch_logger::entities::event::Event;
+use twitch_logger::queue::{EventQueue, OverflowPolicy};
+
+/// More than the queue and the handoff to the handler hold, so that some of them overflow.
+const SENT: usize = 400;
+
+fn chat(number: usize) -> Event {
+    Event::Chat(ChatMessage::new(
+        "forsen".to_string(),
+        "alice".to_string(),
+        number.to_string(),
+        Utc::now(),
+    ))
+}
+
+/// Reads every event until the queue closes. Returns the numbers of the chat messages and the
+/// total dropped according to the gaps.
+async fn drain(rx: &mut Receiver<Event>) -> (Vec<usize>, u64) {
+    let mut numbers = vec![];
+    let mut dropped = 0;
+    while let Some(event) = rx.recv().await {
+        match event {
+            Event::Chat(message) => numbers.push(message.message.parse().unwrap()),
+            Event::Gap(gap) => {
+                assert_eq!(gap.channel, "forsen");
+                dropped += gap.dropped;
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+    (numbers, dropped)
+}
+
+#[tokio::test]
+async fn drops_newest_events_and_marks_the_gap() {
+    let (tx, mut rx) = EventQueue::new(8)
+        .with_policy(OverflowPolicy::DropNewest)
+        .start()
+        .unwrap();
+    for number in 0..SENT {
+        tx.send(chat(number)).await.unwrap();
+    }
+    let stats = tx.handle().stats().await;
+    drop(tx);
+
+    let (numbers, dropped) = drain(&mut rx).await;
+    assert!(dropped > 0);
+    assert_eq!(numbers.len() as u64 + dropped, SENT as u64);
+    assert_eq!(stats.dropped["forsen"], dropped);
+    assert_eq!(numbers.first(), Some(&0));
+    assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test]
+async fn drops_oldest_events_and_marks_the_gap() {
+    let (tx, mut rx) = EventQueue::new(8)
+        .with_policy(OverflowPolicy::DropOldest)
+        .start()
+        .unwrap();
+    for number in 0..SENT {
+        tx.send(chat(number)).await.unwrap();
+    }
+    drop(tx);
+
+    let (numbers, dropped) = drain(&mut rx).await;
+    assert!(dropped > 0);
+    assert_eq!(numbers.len() as u64 + dropped, SENT as u64);
+    assert_eq!(numbers.last(), Some(&(SENT - 1)));
+    assert!(numbers.windows(2).all(|pair| pair[0] < pair[1]));
+}
+
+#[tokio::test]
+async fn blocks_until_there_is_space() {
+    let (tx, mut rx) = EventQueue::new(8).start().unwrap();
+    let handle = tx.handle();
+    let sending = tokio::spawn(async move {
+        for number in 0..SENT {
+            tx.send(chat(number)).await.unwrap();
+        }
+    });
+
+    let (numbers, dropped) = drain(&mut rx).await;
+    sending.await.unwrap();
+    assert_eq!(dropped, 0);
+    assert_eq!(numbers, (0..SENT).collect::<Vec<_>>());
+
+    let stats = handle.stats().await;
+    assert!(stats.dropped.is_empty());
+    assert!(stats.delayed["forsen"] > 0);
+}
+
+#[tokio::test]
+async fn spills_to_disk_in_order() {
+    let path = std::env::temp_dir().join(format!(
+        "twitch-logger-overflow-{}.jsonl",
+        std::process::id()
+    ));
+    let leftover = serde_json::to_string(&chat(0)).unwrap();
+    std::fs::write(&path, format!("{}\n", leftover)).unwrap();
+
+    let (tx, mut rx) = EventQueue::new(8)
+        .with_policy(OverflowPolicy::Spill)
+        .with_overflow_file(path.clone())
+        .start()
+        .unwrap();
+    for number in 1..SENT {
+        tx.send(chat(number)).await.unwrap();
+    }
+    let stats = tx.handle().stats().await;
+    drop(tx);
+
+    let (numbers, dropped) = drain(&mut rx).await;
+    assert_eq!(dropped, 0);
+    assert_eq!(numbers, (0..SENT).collect::<Vec<_>>());
+    assert!(stats.delayed["forsen"] > 0);
+    assert!(std::fs::read_to_string(&path).unwrap().is_empty());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn reads_spilled_events_back_a_queue_at_a_time() {
+    let path = std::env::temp_dir().join(format!(
+        "twitch-logger-leftover-{}.jsonl",
+        std::process::id()
+    ));
+    let leftover = (0..SENT * 4)
+        .map(|number| format!("{}\n", serde_json::to_string(&chat(number)).unwrap()))
+        .collect::<String>();
+    std::fs::write(&path, leftover).unwrap();
+
+    let (tx, mut rx) = EventQueue::new(8)
+        .with_policy(OverflowPolicy::Spill)
+        .with_overflow_file(path.clone())
+        .start()
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(tx.handle().stats().await.queued <= 8);
+    drop(tx);
+
+    let (numbers, dropped) = drain(&mut rx).await;
+    assert_eq!(dropped, 0);
+    assert_eq!(numbers, (0..SENT * 4).collect::<Vec<_>>());
+    assert!(std::fs::read_to_string(&path).unwrap().is_empty());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn fails_once_the_handler_stops() {
+    let (tx, rx) = EventQueue::new(8).start().unwrap();
+    drop(rx);
+
+    let mut result = Ok(());
+    for number in 0..SENT {
+        result = tx.send(chat(number)).await;
+        if result.is_err() {
+            break;
+        }
+    }
+    assert!(result.is_err());
+}
@@ -0,0 +1,103 @@
+mod common;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use common::PRIVMSG;
+use twitch_logger::entities::event::Event;
+use twitch_logger::error::Error;
+use twitch_logger::replay::parse_replay_line;
+
+const JOIN: &str = ":carol!carol@carol.tmi.twitch.tv JOIN #chan";
+
+fn six_pm() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2023, 4, 1, 18, 0, 0).unwrap()
+}
+
+fn parse(line: &str) -> Event {
+    parse_replay_line(line).unwrap().unwrap()
+}
+
+/// Asserts that `event` was timed when it was parsed, not by anything in its line.
+fn assert_parsed_now(event: &Event) {
+    let age = Utc::now() - event.sent_at();
+    assert!(
+        age >= Duration::zero() && age < Duration::minutes(1),
+        "{}",
+        event.sent_at()
+    );
+}
+
+#[test]
+fn reads_rfc3339_prefixes() {
+    let event = parse(&format!("2023-04-01T18:00:00Z {}", JOIN));
+    assert_eq!(event.sent_at(), six_pm());
+
+    let event = parse(&format!("2023-04-01T20:00:00.250+02:00  {}", JOIN));
+    assert_eq!(event.sent_at(), six_pm() + Duration::milliseconds(250));
+}
+
+#[test]
+fn reads_bracketed_prefixes() {
+    for prefix in [
+        "[2023-04-01 18:00:00] ",
+        "[2023-04-01T18:00:00]",
+        "[2023-04-01 18:00:00.000]  ",
+        "[2023-04-01T20:00:00+02:00] ",
+    ] {
+        let event = parse(&format!("{}{}", prefix, JOIN));
+        assert_eq!(event.sent_at(), six_pm(), "{}", prefix);
+    }
+}
+
+#[test]
+fn reads_lines_without_a_prefix() {
+    let event = parse(JOIN);
+    assert!(matches!(event, Event::Presence(_)));
+    assert_parsed_now(&event);
+}
+
+#[test]
+fn times_messages_by_twitch_over_the_prefix() {
+    let event = parse(&format!("[2023-04-02 09:30:00] {}", PRIVMSG));
+    assert_eq!(event.sent_at(), six_pm());
+
+    let event = parse(PRIVMSG);
+    assert_eq!(event.sent_at(), six_pm());
+}
+
+#[test]
+fn rejects_malformed_prefixes() {
+    for prefix in [
+        "[yesterday] ",
+        "[2023-04-01 25:00:00] ",
+        "[2023-04-01 18:00:00 ",
+        "2023-04-01T25:00:00Z ",
+        "2023-04-01 18:00:00 ",
+    ] {
+        let line = format!("{}{}", prefix, JOIN);
+        match parse_replay_line(&line) {
+            Err(Error::FailedToParse { key, value, .. }) => {
+                assert_eq!(key, "IRC message");
+                assert_eq!(value, line);
+            }
+            result => panic!("expected {:?} to be rejected, got {:?}", line, result),
+        }
+    }
+}
+
+#[test]
+fn does_not_take_tags_for_a_timestamp() {
+    for line in [
+        format!("@time=2023-04-01T18:00:00.000Z {}", JOIN),
+        format!("@2023-04-01T18:00:00Z {}", JOIN),
+    ] {
+        let event = parse(&line);
+        assert!(matches!(event, Event::Presence(_)), "{}", line);
+        assert_parsed_now(&event);
+    }
+
+    let event = parse(&format!(
+        "[2023-04-01 17:00:00] @time=2023-04-01T16:00:00Z {}",
+        JOIN
+    ));
+    assert_eq!(event.sent_at(), six_pm() - Duration::hours(1));
+}
@@ -0,0 +1,78 @@
+use std::io::Write;
+use std::path::PathBuf;
+use twitch_logger::entities::event::Event;
+use twitch_logger::replay::parse_replay_line;
+use twitch_logger::spool::Spool;
+
+fn join(user: &str) -> Event {
+    let line = format!(":{0}!{0}@{0}.tmi.twitch.tv JOIN #chan", user);
+    parse_replay_line(&line).unwrap().unwrap()
+}
+
+fn spool_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "twitch-logger-spool-{}-{}.jsonl",
+        name,
+        std::process::id()
+    ))
+}
+
+async fn pending(spool: &Spool) -> Vec<String> {
+    let events = spool.pending().await.unwrap();
+    events.iter().map(|event| event.to_string()).collect()
+}
+
+#[tokio::test]
+async fn cuts_off_an_event_torn_by_a_crash() {
+    let path = spool_path("torn");
+    let mut spool = Spool::open(path.clone()).await.unwrap();
+    spool.append(&[join("alice")]).await.unwrap();
+    drop(spool);
+
+    let bob = serde_json::to_string(&join("bob")).unwrap();
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(&bob.as_bytes()[..bob.len() / 2]).unwrap();
+    drop(file);
+
+    let mut spool = Spool::open(path.clone()).await.unwrap();
+    spool.append(&[join("carol")]).await.unwrap();
+    assert_eq!(
+        pending(&spool).await,
+        [join("alice").to_string(), join("carol").to_string()]
+    );
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn drops_the_events_pending_returned() {
+    let path = spool_path("corrupt");
+    let mut spool = Spool::open(path.clone()).await.unwrap();
+    spool.append(&[join("alice")]).await.unwrap();
+    drop(spool);
+
+    let mut file = std::fs::OpenOptions::new()
+        .append(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(b"{\"Chat\": not json\n").unwrap();
+    drop(file);
+
+    let mut spool = Spool::open(path.clone()).await.unwrap();
+    spool.append(&[join("bob"), join("carol")]).await.unwrap();
+    let events = pending(&spool).await;
+    assert_eq!(events.len(), 3);
+
+    // The handler numbers the events `pending` returned: alice and bob are written.
+    spool.drop_first(2).await.unwrap();
+    assert_eq!(pending(&spool).await, [join("carol").to_string()]);
+
+    spool.append(&[join("dave")]).await.unwrap();
+    assert_eq!(
+        pending(&spool).await,
+        [join("carol").to_string(), join("dave").to_string()]
+    );
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,483 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use sqlx::sqlite::SqlitePoolOptions;
+use sqlx::SqlitePool;
+use twitch_logger::config::Config;
+use twitch_logger::coverage::{coverage, CoverageOptions, Interval};
+use twitch_logger::entities::event::Event;
+use twitch_logger::entities::gap::Gap;
+use twitch_logger::entities::lifecycle::{Lifecycle, LifecycleKind};
+use twitch_logger::entities::presence::PresenceSession;
+use twitch_logger::entities::room_state::{ChannelState, FollowersOnly};
+use twitch_logger::logger::migrations::SQLITE_MIGRATIONS;
+use twitch_logger::logger::sqlite_logger::SqliteLogger;
+use twitch_logger::logger::storage::{self, Storage};
+use twitch_logger::replay::parse_replay_line;
+
+const LINES: [&str; 6] = [
+    "[2023-04-01 18:00:00] :carol!carol@carol.tmi.twitch.tv JOIN #chan",
+    "@badge-info=subscriber/5;badges=subscriber/3;color=#FF0000;display-name=Foo;emotes=25:0-4;id=abc-9;room-id=42;tmi-sent-ts=1680372300000;user-id=7 :foo!foo@foo.tmi.twitch.tv PRIVMSG #chan :Kappa hello",
+    "@login=foo;room-id=;target-msg-id=abc-9;tmi-sent-ts=1680372360000 :tmi.twitch.tv CLEARMSG #chan :Kappa hello",
+    "[2023-04-01 18:07:00] @slow=30;room-id=42 :tmi.twitch.tv ROOMSTATE #chan",
+    "@badge-info=;badges=;color=;display-name=Mod;emotes=;flags=;id=aa-9;login=mod;mod=1;msg-id=announcement;msg-param-color=PRIMARY;room-id=42;subscriber=0;system-msg=;tmi-sent-ts=1680372480000;user-id=2;user-type=mod :tmi.twitch.tv USERNOTICE #chan :Hello everyone",
+    "[2023-04-01 18:09:00] :dave!dave@dave.tmi.twitch.tv JOIN #chan",
+];
+
+fn config(db_url: &str) -> Config {
+    Config {
+        db_url: Some(db_url.to_string()),
+        db_table: Some("chat".to_string()),
+        ..Config::default()
+    }
+}
+
+fn events() -> Vec<Event> {
+    LINES
+        .iter()
+        .map(|line| parse_replay_line(line).unwrap().unwrap())
+        .collect()
+}
+
+async fn memory_pool() -> SqlitePool {
+    SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap()
+}
+
+async fn count(pool: &SqlitePool, table: &str) -> i64 {
+    sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn migrates_once() {
+    let pool = memory_pool().await;
+    let logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+
+    let migrations = SQLITE_MIGRATIONS.len();
+    assert_eq!(logger.pending_migrations().await.unwrap().len(), migrations);
+    assert_eq!(logger.migrate().await.unwrap().len(), migrations);
+    assert!(logger.pending_migrations().await.unwrap().is_empty());
+    assert!(logger.migrate().await.unwrap().is_empty());
+    assert_eq!(count(&pool, "chat_migrations").await, migrations as i64);
+}
+
+#[tokio::test]
+async fn reports_unreadable_migration_history() {
+    let pool = memory_pool().await;
+    let logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    sqlx::query("CREATE TABLE chat_migrations (applied TEXT)")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    match logger.pending_migrations().await {
+        Err(e) => assert!(e.to_string().contains("no such column"), "{}", e),
+        Ok(pending) => panic!("read {} pending migrations", pending.len()),
+    }
+}
+
+#[tokio::test]
+async fn refuses_to_add_unique_keys_over_duplicates() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    logger.migrate().await.unwrap();
+
+    // Back to before version 4, with every event logged twice.
+    for statement in [
+        "DROP INDEX chat_message_id_key",
+        "DROP INDEX chat_user_notices_message_id_key",
+        "DROP INDEX chat_moderation_event_key",
+        "DELETE FROM chat_migrations WHERE version = 4",
+    ] {
+        sqlx::query(statement).execute(&pool).await.unwrap();
+    }
+    logger.create_log_batch(&events()).await.unwrap();
+    logger.create_log_batch(&events()).await.unwrap();
+
+    let pending = logger.pending_migrations().await.unwrap();
+    assert_eq!(pending.iter().map(|m| m.version).collect::<Vec<_>>(), [4]);
+    let error = match logger.migrate().await {
+        Ok(_) => panic!("migrated over duplicates"),
+        Err(e) => e.to_string(),
+    };
+    assert!(error.contains("version 4"), "{}", error);
+    assert!(
+        error.contains(
+            "1 duplicate chat messages, 1 duplicate user notices, 1 duplicate moderation events"
+        ),
+        "{}",
+        error
+    );
+    assert_eq!(count(&pool, "chat").await, 2);
+    assert_eq!(count(&pool, "chat_migrations").await, 8);
+}
+
+#[tokio::test]
+async fn refuses_to_add_event_keys_over_duplicates() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    logger.migrate().await.unwrap();
+
+    // Back to before version 9, with every event logged twice.
+    for statement in [
+        "DROP INDEX chat_room_state_event_key",
+        "DROP INDEX chat_presence_event_key",
+        "DELETE FROM chat_migrations WHERE version = 9",
+    ] {
+        sqlx::query(statement).execute(&pool).await.unwrap();
+    }
+    logger.create_log_batch(&events()).await.unwrap();
+    logger.create_log_batch(&events()).await.unwrap();
+
+    let error = match logger.migrate().await {
+        Ok(_) => panic!("migrated over duplicates"),
+        Err(e) => e.to_string(),
+    };
+    assert!(error.contains("version 9"), "{}", error);
+    assert!(
+        error.contains("1 duplicate room state changes, 2 duplicate presence events"),
+        "{}",
+        error
+    );
+    assert_eq!(count(&pool, "chat_presence").await, 4);
+}
+
+#[tokio::test]
+async fn logs_every_event_type() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    logger.migrate().await.unwrap();
+
+    let mut events = events();
+    let mut gap = Gap::new("chan".to_string(), Utc::now());
+    gap.record(Utc::now());
+    events.push(Event::Gap(gap));
+    events.push(Event::Lifecycle(Lifecycle::new(
+        "chan".to_string(),
+        LifecycleKind::Started {
+            version: "1.0.0".to_string(),
+        },
+    )));
+    logger.create_log_batch(&events).await.unwrap();
+    logger.create_log_batch(&events[6..]).await.unwrap();
+
+    assert_eq!(count(&pool, "chat").await, 1);
+    assert_eq!(count(&pool, "chat_gaps").await, 1);
+    assert_eq!(count(&pool, "chat_lifecycle").await, 1);
+    assert_eq!(count(&pool, "chat_moderation").await, 1);
+    assert_eq!(count(&pool, "chat_room_state").await, 1);
+    assert_eq!(count(&pool, "chat_user_notices").await, 1);
+    assert_eq!(count(&pool, "chat_presence").await, 2);
+    assert_eq!(count(&pool, "chat_presence_sessions").await, 2);
+
+    let target_user_id: Option<String> =
+        sqlx::query_scalar("SELECT target_user_id FROM chat_moderation")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(target_user_id.as_deref(), Some("7"));
+}
+
+#[tokio::test]
+async fn skips_duplicates_in_bulk_inserts() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone())
+        .unwrap()
+        .with_bulk_insert_threshold(1);
+    logger.migrate().await.unwrap();
+
+    let events = events();
+    logger.create_log_batch(&events).await.unwrap();
+    logger.create_log_batch(&events).await.unwrap();
+
+    assert_eq!(count(&pool, "chat").await, 1);
+    assert_eq!(count(&pool, "chat_user_notices").await, 1);
+    assert_eq!(count(&pool, "chat_moderation").await, 1);
+}
+
+#[tokio::test]
+async fn replays_without_duplicating_events() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    logger.migrate().await.unwrap();
+
+    let mut events = events();
+    events.push(
+        parse_replay_line("[2023-04-01 18:15:00] :carol!carol@carol.tmi.twitch.tv PART #chan")
+            .unwrap()
+            .unwrap(),
+    );
+    logger.create_log_batch(&events).await.unwrap();
+    logger.create_log_batch(&events).await.unwrap();
+
+    assert_eq!(count(&pool, "chat_room_state").await, 1);
+    assert_eq!(count(&pool, "chat_presence").await, 3);
+    let sessions = logger
+        .sessions_between("chan", at(18, 0), at(19, 0))
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions[0].left_at, Some(at(18, 15)));
+    assert_eq!(sessions[1].left_at, None);
+}
+
+#[tokio::test]
+async fn closes_stale_sessions_at_last_activity() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    logger.migrate().await.unwrap();
+    logger.create_log_batch(&events()).await.unwrap();
+
+    assert_eq!(logger.close_stale_sessions().await.unwrap(), 2);
+
+    let left_at: Vec<String> = sqlx::query_scalar(
+        "SELECT left_at FROM chat_presence_sessions WHERE close_reason = 'restart'",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(left_at.len(), 2);
+    assert!(left_at
+        .iter()
+        .all(|at| at.starts_with("2023-04-01T18:09:00")));
+}
+
+fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2023, 4, 1, hour, minute, 0).unwrap()
+}
+
+#[tokio::test]
+async fn closes_sessions_when_the_logger_leaves_a_channel() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    logger.migrate().await.unwrap();
+    logger.create_log_batch(&events()).await.unwrap();
+
+    let left = |channel: &str, event: LifecycleKind, recorded_at| {
+        Event::Lifecycle(Lifecycle {
+            channel: channel.to_string(),
+            event,
+            recorded_at,
+        })
+    };
+    logger
+        .create_log_batch(&[
+            left("other", LifecycleKind::Parted, at(18, 10)),
+            left("chan", LifecycleKind::Joined, at(18, 10)),
+        ])
+        .await
+        .unwrap();
+    let sessions = logger
+        .sessions_between("chan", at(18, 0), at(19, 0))
+        .await
+        .unwrap();
+    assert!(sessions.iter().all(|session| session.left_at.is_none()));
+
+    logger
+        .create_log_batch(&[left("chan", LifecycleKind::Parted, at(18, 20))])
+        .await
+        .unwrap();
+    let sessions = logger
+        .sessions_between("chan", at(18, 0), at(19, 0))
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 2);
+    for session in sessions {
+        assert_eq!(session.left_at, Some(at(18, 20)));
+        assert_eq!(session.close_reason.as_deref(), Some("part"));
+    }
+    assert_eq!(logger.close_stale_sessions().await.unwrap(), 0);
+
+    let rejoin =
+        parse_replay_line("[2023-04-01 18:30:00] :carol!carol@carol.tmi.twitch.tv JOIN #chan")
+            .unwrap()
+            .unwrap();
+    let disconnected = LifecycleKind::Disconnected {
+        reason: "connection closed by the server".to_string(),
+    };
+    logger
+        .create_log_batch(&[rejoin, left("chan", disconnected, at(18, 40))])
+        .await
+        .unwrap();
+    let sessions = logger
+        .sessions_between("chan", at(18, 25), at(19, 0))
+        .await
+        .unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].left_at, Some(at(18, 40)));
+    assert_eq!(sessions[0].close_reason.as_deref(), Some("disconnect"));
+}
+
+#[tokio::test]
+async fn reads_channel_state_at_an_instant() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool).unwrap();
+    logger.migrate().await.unwrap();
+    logger.create_log_batch(&events()).await.unwrap();
+    let updates = [
+        "[2023-04-01 18:20:00] @followers-only=10;subs-only=1;room-id=42 :tmi.twitch.tv ROOMSTATE #chan",
+        "[2023-04-01 18:30:00] @slow=0;room-id=42 :tmi.twitch.tv ROOMSTATE #chan",
+    ]
+    .map(|line| parse_replay_line(line).unwrap().unwrap());
+    logger.create_log_batch(&updates).await.unwrap();
+
+    let state = logger.channel_state_at("chan", at(18, 5)).await.unwrap();
+    assert_eq!(state, ChannelState::default());
+
+    let state = logger.channel_state_at("chan", at(18, 25)).await.unwrap();
+    assert_eq!(
+        state,
+        ChannelState {
+            followers_only: Some(FollowersOnly::Enabled { minutes: 10 }),
+            slow_mode_seconds: Some(30),
+            subs_only: Some(true),
+            ..ChannelState::default()
+        }
+    );
+
+    let state = logger.channel_state_at("chan", at(18, 30)).await.unwrap();
+    assert_eq!(state.slow_mode_seconds, Some(0));
+    assert_eq!(state.subs_only, Some(true));
+
+    let state = logger.channel_state_at("other", at(18, 30)).await.unwrap();
+    assert_eq!(state, ChannelState::default());
+}
+
+#[tokio::test]
+async fn reads_sessions_between() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool).unwrap();
+    logger.migrate().await.unwrap();
+    logger.create_log_batch(&events()).await.unwrap();
+    let part =
+        parse_replay_line("[2023-04-01 18:15:00] :carol!carol@carol.tmi.twitch.tv PART #chan")
+            .unwrap()
+            .unwrap();
+    logger.create_log_batch(&[part]).await.unwrap();
+
+    let users = |sessions: Vec<PresenceSession>| {
+        sessions
+            .into_iter()
+            .map(|session| session.username)
+            .collect::<Vec<_>>()
+    };
+    let sessions = logger
+        .sessions_between("chan", at(18, 10), at(18, 20))
+        .await
+        .unwrap();
+    assert_eq!(sessions[0].left_at, Some(at(18, 15)));
+    assert_eq!(sessions[1].left_at, None);
+    assert_eq!(users(sessions), ["carol", "dave"]);
+
+    let sessions = logger
+        .sessions_between("chan", at(17, 0), at(18, 5))
+        .await
+        .unwrap();
+    assert_eq!(users(sessions), ["carol"]);
+
+    let sessions = logger
+        .sessions_between("chan", at(18, 16), at(19, 0))
+        .await
+        .unwrap();
+    assert_eq!(users(sessions), ["dave"]);
+}
+
+#[tokio::test]
+async fn reads_coverage_records() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool.clone()).unwrap();
+    logger.migrate().await.unwrap();
+
+    let start = Utc.with_ymd_and_hms(2023, 4, 1, 18, 0, 0).unwrap();
+    let mut events = events();
+    events.push(Event::Lifecycle(Lifecycle {
+        channel: "chan".to_string(),
+        event: LifecycleKind::Disconnected {
+            reason: "connection closed by the server".to_string(),
+        },
+        recorded_at: start + Duration::minutes(10),
+    }));
+    events.push(Event::Gap(Gap::new("chan".to_string(), start)));
+    logger.create_log_batch(&events).await.unwrap();
+
+    let records = logger.coverage_records(start).await.unwrap();
+    assert_eq!(
+        records.activity,
+        [("chan".to_string(), start + Duration::minutes(5), 1)]
+    );
+    assert_eq!(records.lifecycle.len(), 1);
+    assert_eq!(
+        records.lifecycle[0].event.reason(),
+        Some("connection closed by the server")
+    );
+    assert_eq!(
+        records.lifecycle[0].recorded_at,
+        start + Duration::minutes(10)
+    );
+    assert_eq!(records.gaps.len(), 1);
+
+    let later = logger
+        .coverage_records(start + Duration::hours(1))
+        .await
+        .unwrap();
+    assert!(later.activity.is_empty() && later.gaps.is_empty());
+    assert_eq!(later.lifecycle.len(), 1);
+    assert_eq!(later.lifecycle[0].event.name(), "disconnected");
+    assert_eq!(later.lifecycle[0].recorded_at, start + Duration::hours(1));
+}
+
+#[tokio::test]
+async fn seeds_coverage_with_the_state_before_since() {
+    let pool = memory_pool().await;
+    let mut logger = SqliteLogger::new(&config("sqlite::memory:"), pool).unwrap();
+    logger.migrate().await.unwrap();
+
+    let lifecycle = |event, recorded_at| {
+        Event::Lifecycle(Lifecycle {
+            channel: "chan".to_string(),
+            event,
+            recorded_at,
+        })
+    };
+    let mut events = events();
+    events.push(lifecycle(LifecycleKind::Connected, at(17, 58)));
+    events.push(lifecycle(LifecycleKind::Joined, at(18, 0)));
+    logger.create_log_batch(&events).await.unwrap();
+
+    let records = logger.coverage_records(at(18, 2)).await.unwrap();
+    assert_eq!(records.lifecycle.len(), 1);
+    assert_eq!(records.lifecycle[0].event, LifecycleKind::Joined);
+
+    let channels = coverage(&records, &CoverageOptions::default());
+    assert_eq!(
+        channels[0].days[0].intervals,
+        [Interval {
+            start: at(18, 2),
+            end: at(18, 6),
+            inferred: false,
+        }]
+    );
+}
+
+#[tokio::test]
+async fn connects_by_scheme() {
+    let mut storage = storage::connect(&config("sqlite::memory:")).await.unwrap();
+    storage.migrate().await.unwrap();
+    storage.create_log_batch(&events()).await.unwrap();
+
+    assert!(storage::connect(&config("mysql://localhost/logs"))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn rejects_schema_qualified_tables() {
+    let mut config = config("sqlite::memory:");
+    config.db_table = Some("logs.chat".to_string());
+
+    assert!(SqliteLogger::new(&config, memory_pool().await).is_err());
+}